@@ -0,0 +1,114 @@
+use chrono::{serde::ts_seconds, DateTime, Utc};
+use sea_orm::{entity::prelude::*, ActiveValue, IntoActiveModel, QueryOrder};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, Default, ToSchema,
+)]
+#[sea_orm(table_name = "AuditLogs")]
+#[schema(as = AuditLog)]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// The user who performed the action, `None` for the system itself.
+    pub actor_id: Option<i32>,
+    /// Dotted action name, e.g. `user.modify`.
+    #[sea_orm(column_type = "String(StringLen::N(64))")]
+    pub action: String,
+    /// What the action was performed on, e.g. `user:42`.
+    #[sea_orm(column_type = "String(StringLen::N(128))", nullable)]
+    pub target: Option<String>,
+    #[sea_orm(column_type = "Json", nullable)]
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<Json>,
+    #[sea_orm(column_type = "Json", nullable)]
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<Json>,
+    #[sea_orm(column_type = "String(StringLen::N(64))", nullable)]
+    pub ip: Option<String>,
+    #[serde(with = "ts_seconds")]
+    #[schema(value_type = i64)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Conditions for [`get_page`], unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub actor_id: Option<i32>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[instrument(name = "audit_log::create", skip_all)]
+pub async fn create<C>(db: &C, model: Model) -> Result<Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let entry = ActiveModel {
+        id: ActiveValue::NotSet,
+        ..model.into_active_model().reset_all()
+    };
+    entry.insert(db).await
+}
+
+/// Fetch one page (0-based) of entries matching the filter, newest first,
+/// together with the total number of matching entries.
+#[instrument(name = "audit_log::get_page", skip_all, fields(page, per_page))]
+pub async fn get_page<C>(
+    db: &C,
+    filter: Filter,
+    page: u64,
+    per_page: u64,
+) -> Result<(Vec<Model>, u64), DbErr>
+where
+    C: ConnectionTrait,
+{
+    let mut query = Entity::find();
+    if let Some(actor_id) = filter.actor_id {
+        query = query.filter(Column::ActorId.eq(actor_id));
+    }
+    if let Some(action) = filter.action {
+        // `user` matches `user.modify`, `user.import`, ...
+        query = query.filter(
+            Column::Action
+                .eq(action.clone())
+                .or(Column::Action.starts_with(format!("{action}."))),
+        );
+    }
+    if let Some(target) = filter.target {
+        query = query.filter(Column::Target.eq(target));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(Column::CreatedAt.lt(to));
+    }
+    let paginator = query.order_by_desc(Column::Id).paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let items = paginator.fetch_page(page).await?;
+    Ok((items, total))
+}
+
+/// Remove every entry created before `edge`, returns the number of removed
+/// entries.
+#[instrument(name = "audit_log::delete_before", skip_all)]
+pub async fn delete_before<C>(db: &C, edge: DateTime<Utc>) -> Result<u64, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let res = Entity::delete_many()
+        .filter(Column::CreatedAt.lt(edge))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
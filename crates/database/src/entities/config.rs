@@ -1,5 +1,6 @@
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, ActiveValue, IntoActiveModel};
 use serde::{Deserialize, Serialize};
+use tracing::instrument;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, Default)]
 #[sea_orm(table_name = "Configs")]
@@ -15,3 +16,36 @@ pub struct Model {
 pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
+
+/// The current configuration, there is at most one row.
+#[instrument(name = "config::get", skip_all)]
+pub async fn get<C>(db: &C) -> Result<Option<Model>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::find().one(db).await
+}
+
+/// Replace the configuration, creating the row when there is none yet.
+#[instrument(name = "config::save", skip_all)]
+pub async fn save<C>(db: &C, begin_week: i32, skip_weeks: Option<String>) -> Result<Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    match get(db).await? {
+        Some(current) => {
+            let mut config = current.into_active_model();
+            config.begin_week = ActiveValue::Set(begin_week);
+            config.skip_weeks = ActiveValue::Set(skip_weeks);
+            config.update(db).await
+        }
+        None => {
+            let config = ActiveModel {
+                id: ActiveValue::NotSet,
+                begin_week: ActiveValue::Set(begin_week),
+                skip_weeks: ActiveValue::Set(skip_weeks),
+            };
+            config.insert(db).await
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use chrono::{
+    serde::{ts_seconds, ts_seconds_option},
+    DateTime, Utc,
+};
+use sea_orm::{entity::prelude::*, ActiveValue, QueryOrder};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// A feed token of a user. Only the SHA-256 hash of the token is stored, the
+/// plaintext is shown to the user once when the token is created.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, Default, ToSchema,
+)]
+#[sea_orm(table_name = "FeedTokens")]
+#[schema(as = FeedToken)]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    #[sea_orm(column_type = "String(StringLen::N(64))")]
+    pub label: String,
+    /// Hex encoded SHA-256 of the token.
+    #[serde(skip)]
+    #[sea_orm(column_type = "String(StringLen::N(64))", unique)]
+    pub token_hash: String,
+    #[serde(with = "ts_seconds")]
+    #[schema(value_type = i64)]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "ts_seconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(with = "ts_seconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[instrument(name = "feed_token::get", skip_all, fields(id))]
+pub async fn get<C>(db: &C, id: i32) -> Result<Option<Model>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::find_by_id(id).one(db).await
+}
+
+#[instrument(name = "feed_token::get_by_hash", skip_all)]
+pub async fn get_by_hash<C>(db: &C, token_hash: &str) -> Result<Option<Model>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::find()
+        .filter(Column::TokenHash.eq(token_hash))
+        .one(db)
+        .await
+}
+
+/// All tokens of a user, including revoked ones, newest first.
+#[instrument(name = "feed_token::get_user_list", skip_all, fields(user_id))]
+pub async fn get_user_list<C>(db: &C, user_id: i32) -> Result<Vec<Model>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::find()
+        .filter(Column::UserId.eq(user_id))
+        .order_by_desc(Column::Id)
+        .all(db)
+        .await
+}
+
+#[instrument(name = "feed_token::count_active", skip_all, fields(user_id))]
+pub async fn count_active<C>(db: &C, user_id: i32) -> Result<u64, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::find()
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::RevokedAt.is_null())
+        .count(db)
+        .await
+}
+
+#[instrument(name = "feed_token::create", skip_all, fields(user_id))]
+pub async fn create<C>(
+    db: &C,
+    user_id: i32,
+    label: String,
+    token_hash: String,
+) -> Result<Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let token = ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id),
+        label: ActiveValue::Set(label),
+        token_hash: ActiveValue::Set(token_hash),
+        created_at: ActiveValue::Set(Utc::now()),
+        last_used_at: ActiveValue::Set(None),
+        revoked_at: ActiveValue::Set(None),
+    };
+    token.insert(db).await
+}
+
+/// Record that a token has just been used.
+#[instrument(name = "feed_token::touch", skip_all, fields(id))]
+pub async fn touch<C>(db: &C, id: i32) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::update_many()
+        .col_expr(Column::LastUsedAt, Expr::value(Utc::now()))
+        .filter(Column::Id.eq(id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Revoke one token of a user, returns whether an active token was revoked.
+#[instrument(name = "feed_token::revoke", skip_all, fields(user_id, id))]
+pub async fn revoke<C>(db: &C, user_id: i32, id: i32) -> Result<bool, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let res = Entity::update_many()
+        .col_expr(Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(Column::Id.eq(id))
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Revoke every active token of a user, returns the number of revoked
+/// tokens.
+#[instrument(name = "feed_token::revoke_all", skip_all, fields(user_id))]
+pub async fn revoke_all<C>(db: &C, user_id: i32) -> Result<u64, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let res = Entity::update_many()
+        .col_expr(Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
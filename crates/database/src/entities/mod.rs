@@ -1,3 +1,5 @@
+pub mod audit_log;
 pub mod config;
+pub mod feed_token;
 pub mod report;
 pub mod user;
@@ -1,13 +1,19 @@
 use chrono::{serde::ts_seconds, DateTime, Datelike, Duration, Utc};
 use sea_orm::{
-    entity::prelude::*, ActiveValue, FromQueryResult, IntoActiveModel, JoinType, QuerySelect,
+    entity::prelude::*, ActiveValue, Condition, FromQueryResult, IntoActiveModel, JoinType,
+    QueryOrder, QuerySelect,
 };
 use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
 
 use crate::user;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, Default)]
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, Default, ToSchema,
+)]
 #[sea_orm(table_name = "Reports")]
+#[schema(as = Report)]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
@@ -18,7 +24,127 @@ pub struct Model {
     #[sea_orm(column_type = "Text", nullable)]
     pub likes: Option<String>,
     #[serde(with = "ts_seconds")]
+    #[schema(value_type = i64)]
     pub date: DateTime<Utc>,
+    pub visibility: Visibility,
+}
+
+/// Who may read a report.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// The author and the mentors allowed to read private reports.
+    #[sea_orm(num_value = 0)]
+    Private,
+    /// Every logged-in member.
+    #[default]
+    #[sea_orm(num_value = 1)]
+    Team,
+    /// Everyone, including the public endpoints and feeds.
+    #[sea_orm(num_value = 2)]
+    Public,
+}
+
+/// Who is reading reports. Every query that lists or fetches reports for a
+/// reader takes an audience, so that the visibility rules are applied the
+/// same way everywhere.
+#[derive(Clone, Debug)]
+pub enum Audience {
+    /// The server itself, e.g. for submission bookkeeping. Sees everything.
+    System,
+    /// Not logged in. Sees public reports of users who are not hidden.
+    Anonymous,
+    /// A logged-in member. Always sees their own reports. Sees team and public
+    /// reports plus the private reports granted by `private`, limited to
+    /// users who are not hidden unless `include_hidden` is set.
+    Member {
+        user_id: i32,
+        private: PrivateAccess,
+        include_hidden: bool,
+    },
+}
+
+/// Which private reports of other members a reader may see.
+#[derive(Clone, Debug)]
+pub enum PrivateAccess {
+    None,
+    /// Private reports of authors in the given direction.
+    Direction(String),
+    All,
+}
+
+impl Audience {
+    /// Whether the audience may read a report with the given visibility by
+    /// `author`. The in-memory counterpart of [`Audience::condition`], for
+    /// reports that are not read from the database, e.g. live events.
+    pub fn can_read(&self, author: &user::Model, visibility: Visibility) -> bool {
+        match self {
+            Audience::System => true,
+            Audience::Anonymous => visibility == Visibility::Public && !author.is_hidden,
+            Audience::Member {
+                user_id,
+                private,
+                include_hidden,
+            } => {
+                if author.id == *user_id {
+                    return true;
+                }
+                let readable = visibility != Visibility::Private
+                    || match private {
+                        PrivateAccess::None => false,
+                        PrivateAccess::Direction(direction) => {
+                            author.direction.as_ref() == Some(direction)
+                        }
+                        PrivateAccess::All => true,
+                    };
+                readable && (*include_hidden || !author.is_hidden)
+            }
+        }
+    }
+
+    /// The condition on `Reports` joined with their author in `Users`.
+    fn condition(&self) -> Condition {
+        match self {
+            Audience::System => Condition::all(),
+            Audience::Anonymous => Condition::all()
+                .add(Column::Visibility.eq(Visibility::Public))
+                .add(user::Column::IsHidden.eq(false)),
+            Audience::Member {
+                user_id,
+                private,
+                include_hidden,
+            } => {
+                let readable = Condition::any().add(Column::Visibility.ne(Visibility::Private));
+                let readable = match private {
+                    PrivateAccess::None => readable,
+                    PrivateAccess::Direction(direction) => {
+                        readable.add(user::Column::Direction.eq(direction.clone()))
+                    }
+                    PrivateAccess::All => Condition::all(),
+                };
+                let mut others = Condition::all().add(readable);
+                if !include_hidden {
+                    others = others.add(user::Column::IsHidden.eq(false));
+                }
+                Condition::any()
+                    .add(Column::AuthorId.eq(*user_id))
+                    .add(others)
+            }
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, FromQueryResult)]
@@ -31,6 +157,7 @@ pub struct ExModel {
     pub likes: Option<String>,
     #[serde(with = "ts_seconds")]
     pub date: DateTime<Utc>,
+    pub visibility: Visibility,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -53,6 +180,7 @@ impl Related<super::user::Entity> for Entity {
 
 impl ActiveModelBehavior for ActiveModel {}
 
+#[instrument(name = "report::get", skip_all, fields(user_id, week))]
 pub async fn get<C>(db: &C, user_id: i32, week: i32) -> Result<Option<Model>, DbErr>
 where
     C: ConnectionTrait,
@@ -64,7 +192,13 @@ where
         .await
 }
 
-pub async fn get_ex<C>(db: &C, user_id: i32, week: i32) -> Result<Option<ExModel>, DbErr>
+#[instrument(name = "report::get_ex", skip_all, fields(user_id, week))]
+pub async fn get_ex<C>(
+    db: &C,
+    user_id: i32,
+    week: i32,
+    audience: &Audience,
+) -> Result<Option<ExModel>, DbErr>
 where
     C: ConnectionTrait,
 {
@@ -73,12 +207,33 @@ where
         .column_as(user::Column::Name, "author_name")
         .filter(Column::AuthorId.eq(user_id))
         .filter(Column::Week.eq(week))
+        .filter(audience.condition())
         .into_model()
         .one(db)
         .await
 }
 
-pub async fn get_user_list<C>(db: &C, user_id: i32) -> Result<Vec<Model>, DbErr>
+/// Fetch a single report by id if the audience may read it.
+#[instrument(name = "report::get_ex_by_id", skip_all, fields(id))]
+pub async fn get_ex_by_id<C>(db: &C, id: i32, audience: &Audience) -> Result<Option<ExModel>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Entity::find_by_id(id)
+        .join(JoinType::InnerJoin, Relation::Author.def())
+        .column_as(user::Column::Name, "author_name")
+        .filter(audience.condition())
+        .into_model()
+        .one(db)
+        .await
+}
+
+#[instrument(name = "report::get_user_list", skip_all, fields(user_id))]
+pub async fn get_user_list<C>(
+    db: &C,
+    user_id: i32,
+    audience: &Audience,
+) -> Result<Vec<Model>, DbErr>
 where
     C: ConnectionTrait,
 {
@@ -89,13 +244,17 @@ where
             Column::Id,
             Column::AuthorId,
             Column::Date,
+            Column::Visibility,
         ])
+        .join(JoinType::InnerJoin, Relation::Author.def())
         .filter(Column::AuthorId.eq(user_id))
+        .filter(audience.condition())
         .all(db)
         .await
 }
 
-pub async fn get_week_list<C>(db: &C, week: i32) -> Result<Vec<ExModel>, DbErr>
+#[instrument(name = "report::get_week_list", skip_all, fields(week))]
+pub async fn get_week_list<C>(db: &C, week: i32, audience: &Audience) -> Result<Vec<ExModel>, DbErr>
 where
     C: ConnectionTrait,
 {
@@ -106,16 +265,23 @@ where
             Column::Id,
             Column::AuthorId,
             Column::Date,
+            Column::Visibility,
         ])
         .join(JoinType::InnerJoin, Relation::Author.def())
         .column_as(user::Column::Name, "author_name")
         .filter(Column::Week.eq(week))
+        .filter(audience.condition())
         .into_model()
         .all(db)
         .await
 }
 
-pub async fn get_user_ex_list<C>(db: &C, user_id: i32) -> Result<Vec<ExModel>, DbErr>
+#[instrument(name = "report::get_user_ex_list", skip_all, fields(user_id))]
+pub async fn get_user_ex_list<C>(
+    db: &C,
+    user_id: i32,
+    audience: &Audience,
+) -> Result<Vec<ExModel>, DbErr>
 where
     C: ConnectionTrait,
 {
@@ -123,12 +289,14 @@ where
         .join(JoinType::InnerJoin, Relation::Author.def())
         .column_as(user::Column::Name, "author_name")
         .filter(Column::AuthorId.eq(user_id))
+        .filter(audience.condition())
         .into_model()
         .all(db)
         .await
 }
 
-pub async fn get_index_list<C>(db: &C) -> Result<Vec<Model>, DbErr>
+#[instrument(name = "report::get_index_list", skip_all)]
+pub async fn get_index_list<C>(db: &C, audience: &Audience) -> Result<Vec<Model>, DbErr>
 where
     C: ConnectionTrait,
 {
@@ -149,15 +317,96 @@ where
             Column::Id,
             Column::AuthorId,
             Column::Date,
+            Column::Visibility,
         ])
         .join(JoinType::InnerJoin, Relation::Author.def())
         .filter(user::Column::IsHidden.eq(false))
         .filter(Column::Week.gt(edge))
+        .filter(audience.condition())
         .all(db)
         .await
 }
 
-pub async fn create<C>(db: &C, user_id: i32, week: i32, content: String) -> Result<Model, DbErr>
+/// Fetch one page (0-based) of an author's reports, newest week first,
+/// together with the total number of reports the audience may read. Like
+/// [`get_user_list`], the content is left out.
+#[instrument(
+    name = "report::get_user_page",
+    skip_all,
+    fields(user_id, page, per_page)
+)]
+pub async fn get_user_page<C>(
+    db: &C,
+    user_id: i32,
+    audience: &Audience,
+    page: u64,
+    per_page: u64,
+) -> Result<(Vec<Model>, u64), DbErr>
+where
+    C: ConnectionTrait,
+{
+    let paginator = Entity::find()
+        .select_only()
+        .columns(vec![
+            Column::Week,
+            Column::Id,
+            Column::AuthorId,
+            Column::Date,
+            Column::Visibility,
+        ])
+        .join(JoinType::InnerJoin, Relation::Author.def())
+        .filter(Column::AuthorId.eq(user_id))
+        .filter(audience.condition())
+        .order_by_desc(Column::Week)
+        .paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let items = paginator.fetch_page(page).await?;
+    Ok((items, total))
+}
+
+/// Fetch one page (0-based) of a week's reports, ordered by author, together
+/// with the total number of reports the audience may read. Like
+/// [`get_week_list`], the content is left out.
+#[instrument(name = "report::get_week_page", skip_all, fields(week, page, per_page))]
+pub async fn get_week_page<C>(
+    db: &C,
+    week: i32,
+    audience: &Audience,
+    page: u64,
+    per_page: u64,
+) -> Result<(Vec<ExModel>, u64), DbErr>
+where
+    C: ConnectionTrait,
+{
+    let paginator = Entity::find()
+        .select_only()
+        .columns(vec![
+            Column::Week,
+            Column::Id,
+            Column::AuthorId,
+            Column::Date,
+            Column::Visibility,
+        ])
+        .join(JoinType::InnerJoin, Relation::Author.def())
+        .column_as(user::Column::Name, "author_name")
+        .filter(Column::Week.eq(week))
+        .filter(audience.condition())
+        .order_by_asc(Column::AuthorId)
+        .into_model::<ExModel>()
+        .paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let items = paginator.fetch_page(page).await?;
+    Ok((items, total))
+}
+
+#[instrument(name = "report::create", skip_all, fields(user_id, week))]
+pub async fn create<C>(
+    db: &C,
+    user_id: i32,
+    week: i32,
+    content: String,
+    visibility: Visibility,
+) -> Result<Model, DbErr>
 where
     C: ConnectionTrait,
 {
@@ -168,6 +417,7 @@ where
         content: Some(content),
         likes: None,
         date: Utc::now(),
+        visibility,
     };
     let model = model.into_active_model();
     let model = ActiveModel {
@@ -177,6 +427,7 @@ where
     model.insert(db).await
 }
 
+#[instrument(name = "report::get_by_id", skip_all, fields(id))]
 pub async fn get_by_id<C>(db: &C, id: i32) -> Result<Option<Model>, DbErr>
 where
     C: ConnectionTrait,
@@ -184,22 +435,29 @@ where
     Entity::find_by_id(id).one(db).await
 }
 
+/// Save the content and visibility of an edited report, its date becomes now.
+#[instrument(name = "report::update", skip_all)]
 pub async fn update<C>(db: &C, model: Model) -> Result<Model, DbErr>
 where
     C: ConnectionTrait,
 {
-    // Convert the incoming Model into an ActiveModel and update only the fields
-    // that should change. Using `reset_all()` here previously caused optional
-    // fields like `content` to become NotSet, which leads to clearing them in
-    // the DB on update. Instead, preserve the model's fields and only set the
-    // `date` to now.
+    edit(model).update(db).await
+}
+
+fn edit(model: Model) -> ActiveModel {
+    // The converted fields are all `Unchanged`, which an UPDATE skips. Mark
+    // the ones an edit may change as `Set`, and leave `likes` alone so that a
+    // concurrent like is not overwritten.
     let mut am = model.into_active_model();
+    am.content.reset();
+    am.visibility.reset();
     am.date = ActiveValue::Set(Utc::now());
-    am.update(db).await
+    am
 }
 
 /// Update only the likes column for a given report id. This avoids touching
 /// other fields and is useful for concurrent-like operations.
+#[instrument(name = "report::update_likes_by_id", skip_all, fields(id))]
 pub async fn update_likes_by_id<C>(db: &C, id: i32, likes: Option<String>) -> Result<Model, DbErr>
 where
     C: ConnectionTrait,
@@ -222,3 +480,35 @@ where
         None => Err(DbErr::Custom("report not found after update".to_string())),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DbBackend, QueryTrait, Value};
+
+    use super::*;
+
+    #[test]
+    fn edits_write_content_and_visibility() {
+        let report = Model {
+            id: 7,
+            author_id: 1,
+            week: 20261018,
+            content: Some("final".into()),
+            likes: Some("[2]".into()),
+            visibility: Visibility::Private,
+            ..Default::default()
+        };
+        let update = Entity::update(edit(report)).build(DbBackend::MySql);
+        assert!(
+            update
+                .sql
+                .starts_with("UPDATE `Reports` SET `content` = ?, `date` = ?, `visibility` = ?"),
+            "{}",
+            update.sql
+        );
+        let values = update.values.unwrap().0;
+        assert_eq!(values[0], Value::String(Some(Box::new("final".into()))));
+        assert_eq!(values[2], Value::Int(Some(0)));
+        assert_eq!(values[3], Value::Int(Some(7)));
+    }
+}
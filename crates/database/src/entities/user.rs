@@ -1,5 +1,10 @@
-use sea_orm::{entity::prelude::*, ActiveValue, IntoActiveModel};
-use serde::{Deserialize, Serialize};
+use chrono::{serde::ts_seconds_option, DateTime, Utc};
+use sea_orm::{entity::prelude::*, sea_query::Func, ActiveValue, IntoActiveModel};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::report::Visibility;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, Default)]
 #[sea_orm(table_name = "Users")]
@@ -11,19 +16,80 @@ pub struct Model {
     #[sea_orm(column_type = "Text", nullable)]
     pub email: Option<String>,
     #[sea_orm(column_type = "Text", nullable)]
-    pub feed_token: Option<String>,
-    #[sea_orm(column_type = "Text", nullable)]
     pub direction: Option<String>,
     pub level: i32,
     pub is_banned: bool,
     pub is_hidden: bool,
     pub is_admin: bool,
+    /// When the ban is lifted, `None` means the ban never expires.
+    #[serde(with = "ts_seconds_option")]
+    pub banned_until: Option<DateTime<Utc>>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub ban_reason: Option<String>,
+    /// Visibility of newly submitted reports when the submission does not
+    /// choose one.
+    pub default_visibility: Visibility,
+    /// Assigned role, see [`Model::role`] for the one that applies.
+    #[sea_orm(column_name = "role")]
+    #[serde(rename = "role")]
+    pub assigned_role: Role,
+}
+
+/// Role of a user. `level` is the grade of a member and has no say in it.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    DeriveActiveEnum,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    #[sea_orm(string_value = "member")]
+    Member,
+    #[sea_orm(string_value = "mentor")]
+    Mentor,
+    #[sea_orm(string_value = "direction_lead")]
+    DirectionLead,
+    /// Only `is_admin` makes an admin, an assigned `admin` counts as member.
+    #[sea_orm(string_value = "admin")]
+    Admin,
+}
+
+impl Model {
+    /// The role that applies: admin when `is_admin` is set, the assigned one
+    /// otherwise.
+    pub fn role(&self) -> Role {
+        match (self.is_admin, self.assigned_role) {
+            (true, _) => Role::Admin,
+            (false, Role::Admin) => Role::Member,
+            (false, role) => role,
+        }
+    }
+
+    /// Whether the user is banned at the given moment. A ban whose expiry has
+    /// passed no longer applies, even if `is_banned` is still set.
+    pub fn is_banned_at(&self, now: DateTime<Utc>) -> bool {
+        self.is_banned && self.banned_until.map_or(true, |until| until > now)
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::report::Entity")]
     Report,
+    #[sea_orm(has_many = "super::feed_token::Entity")]
+    FeedToken,
 }
 
 impl Related<super::report::Entity> for Entity {
@@ -32,8 +98,97 @@ impl Related<super::report::Entity> for Entity {
     }
 }
 
+impl Related<super::feed_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::FeedToken.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
+/// A partial update of a user. Only the fields that are present are written
+/// back, everything else keeps its current value. Nullable columns use a
+/// nested option: a missing key leaves the column alone, `null` clears it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct UserPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub email: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub direction: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_banned: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_hidden: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_admin: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_visibility: Option<Visibility>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+
+impl UserPatch {
+    /// Names of the fields present in this patch.
+    pub fn fields(&self) -> Vec<&'static str> {
+        let mut fields = vec![];
+        if self.name.is_some() {
+            fields.push("name");
+        }
+        if self.email.is_some() {
+            fields.push("email");
+        }
+        if self.direction.is_some() {
+            fields.push("direction");
+        }
+        if self.level.is_some() {
+            fields.push("level");
+        }
+        if self.is_banned.is_some() {
+            fields.push("is_banned");
+        }
+        if self.is_hidden.is_some() {
+            fields.push("is_hidden");
+        }
+        if self.is_admin.is_some() {
+            fields.push("is_admin");
+        }
+        if self.default_visibility.is_some() {
+            fields.push("default_visibility");
+        }
+        if self.role.is_some() {
+            fields.push("role");
+        }
+        fields
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields().is_empty()
+    }
+}
+
+/// Deserialize a present key (even `null`) as `Some`, so that it can be told
+/// apart from a missing key once `#[serde(default)]` fills that in as `None`.
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[instrument(name = "user::get", skip_all, fields(user_id))]
 pub async fn get<C>(db: &C, user_id: i32) -> Result<Option<Model>, DbErr>
 where
     C: ConnectionTrait,
@@ -41,6 +196,7 @@ where
     Entity::find_by_id(user_id).one(db).await
 }
 
+#[instrument(name = "user::get_by_name", skip_all)]
 pub async fn get_by_name<C>(db: &C, name: &str) -> Result<Option<Model>, DbErr>
 where
     C: ConnectionTrait,
@@ -48,23 +204,19 @@ where
     Entity::find().filter(Column::Name.eq(name)).one(db).await
 }
 
+/// Emails are matched regardless of case, the stored address may use any.
+#[instrument(name = "user::get_by_email", skip_all)]
 pub async fn get_by_email<C>(db: &C, email: &str) -> Result<Option<Model>, DbErr>
 where
     C: ConnectionTrait,
 {
     Entity::find()
-        .filter(Column::Email.eq(email.to_string()))
+        .filter(Expr::expr(Func::lower(Expr::col(Column::Email))).eq(email.to_lowercase()))
         .one(db)
         .await
 }
 
-pub async fn get_by_feed_token<C>(db: &C, token: &str) -> Result<Option<Model>, DbErr>
-where
-    C: ConnectionTrait,
-{
-    Entity::find().filter(Column::FeedToken.eq(token.to_string())).one(db).await
-}
-
+#[instrument(name = "user::get_list", skip_all, fields(with_hidden))]
 pub async fn get_list<C>(db: &C, with_hidden: bool) -> Result<Vec<Model>, DbErr>
 where
     C: ConnectionTrait,
@@ -79,6 +231,7 @@ where
     }
 }
 
+#[instrument(name = "user::create", skip_all)]
 pub async fn create<C>(db: &C, model: Model) -> Result<Model, DbErr>
 where
     C: ConnectionTrait,
@@ -90,6 +243,7 @@ where
     user.insert(db).await
 }
 
+#[instrument(name = "user::create_list", skip_all)]
 pub async fn create_list<C>(db: &C, models: Vec<Model>) -> Result<(), DbErr>
 where
     C: ConnectionTrait,
@@ -105,6 +259,7 @@ where
     Ok(())
 }
 
+#[instrument(name = "user::update", skip_all)]
 pub async fn update<C>(db: &C, model: Model) -> Result<Model, DbErr>
 where
     C: ConnectionTrait,
@@ -115,3 +270,159 @@ where
     };
     user.update(db).await
 }
+
+/// Apply a [`UserPatch`] to the user with the given id, touching only the
+/// columns present in the patch.
+#[instrument(name = "user::patch", skip_all, fields(user_id))]
+pub async fn patch<C>(db: &C, user_id: i32, patch: UserPatch) -> Result<Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    fn set<T: Into<Value>>(value: Option<T>) -> ActiveValue<T> {
+        match value {
+            Some(v) => ActiveValue::Set(v),
+            None => ActiveValue::NotSet,
+        }
+    }
+
+    if patch.is_empty() {
+        return get(db, user_id)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!("user {user_id}")));
+    }
+    let user = ActiveModel {
+        id: ActiveValue::Unchanged(user_id),
+        name: set(patch.name),
+        email: set(patch.email),
+        direction: set(patch.direction),
+        level: set(patch.level),
+        is_banned: set(patch.is_banned),
+        is_hidden: set(patch.is_hidden),
+        is_admin: set(patch.is_admin),
+        default_visibility: set(patch.default_visibility),
+        assigned_role: set(patch.role),
+        ..Default::default()
+    };
+    user.update(db).await
+}
+
+/// Ban a user, optionally until a given moment and with a reason shown to
+/// them.
+#[instrument(name = "user::ban", skip_all, fields(user_id))]
+pub async fn ban<C>(
+    db: &C,
+    user_id: i32,
+    reason: Option<String>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let user = ActiveModel {
+        id: ActiveValue::Unchanged(user_id),
+        is_banned: ActiveValue::Set(true),
+        banned_until: ActiveValue::Set(until),
+        ban_reason: ActiveValue::Set(reason),
+        ..Default::default()
+    };
+    user.update(db).await
+}
+
+#[instrument(name = "user::unban", skip_all, fields(user_id))]
+pub async fn unban<C>(db: &C, user_id: i32) -> Result<Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let user = ActiveModel {
+        id: ActiveValue::Unchanged(user_id),
+        is_banned: ActiveValue::Set(false),
+        banned_until: ActiveValue::Set(None),
+        ban_reason: ActiveValue::Set(None),
+        ..Default::default()
+    };
+    user.update(db).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn emails_match_regardless_of_case() {
+        use sea_orm::{ConnectionTrait, Database, Schema};
+
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        let backend = db.get_database_backend();
+        let table = Schema::new(backend).create_table_from_entity(Entity);
+        db.execute(backend.build(&table)).await.unwrap();
+        let stored = create(
+            &db,
+            Model {
+                name: "foo".to_string(),
+                email: Some("Foo@Bar.com".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        for email in ["foo@bar.com", "FOO@BAR.COM", "Foo@Bar.com"] {
+            let found = get_by_email(&db, email).await.unwrap();
+            assert_eq!(found.map(|u| u.id), Some(stored.id), "{email}");
+        }
+        assert!(get_by_email(&db, "foo@bar.org").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn bans_apply_until_they_expire() {
+        let now = Utc::now();
+        let user = Model::default();
+        assert!(!user.is_banned_at(now));
+
+        let permanent = Model {
+            is_banned: true,
+            banned_until: None,
+            ban_reason: Some("spam".to_string()),
+            ..Default::default()
+        };
+        assert!(permanent.is_banned_at(now));
+        assert!(permanent.is_banned_at(now + chrono::Duration::days(3650)));
+
+        let until = now + chrono::Duration::hours(1);
+        let temporary = Model {
+            is_banned: true,
+            banned_until: Some(until),
+            ..Default::default()
+        };
+        assert!(temporary.is_banned_at(now));
+        // the ban is over at the moment it ends, without anyone lifting it
+        assert!(!temporary.is_banned_at(until));
+        assert!(!temporary.is_banned_at(until + chrono::Duration::seconds(1)));
+
+        // an expiry alone does not ban
+        let lifted = Model {
+            is_banned: false,
+            ..temporary
+        };
+        assert!(!lifted.is_banned_at(now));
+    }
+
+    #[test]
+    fn patch_tells_absent_null_and_values_apart() {
+        let patch: UserPatch = serde_json::from_str("{}").unwrap();
+        assert!(patch.is_empty());
+
+        let patch: UserPatch =
+            serde_json::from_str(r#"{"email": null, "direction": "web"}"#).unwrap();
+        // null clears the field, a missing key leaves it alone
+        assert_eq!(patch.email, Some(None));
+        assert_eq!(patch.direction, Some(Some("web".to_string())));
+        assert_eq!(patch.name, None);
+        assert_eq!(patch.fields(), ["email", "direction"]);
+
+        // null on a field that cannot be cleared is the same as leaving it out
+        let patch: UserPatch = serde_json::from_str(r#"{"level": null}"#).unwrap();
+        assert!(patch.is_empty());
+        assert!(serde_json::from_str::<UserPatch>(r#"{"level": "1"}"#).is_err());
+    }
+}
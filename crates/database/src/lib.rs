@@ -1,24 +1,70 @@
 mod entities;
+mod migrations;
 
-pub use entities::{config, report, user};
-pub use sea_orm::DbErr;
+pub mod stats;
+
+pub use entities::{audit_log, config, feed_token, report, user};
+pub use migrations::Migrator;
+pub use sea_orm::{DbErr, TransactionTrait};
 use sea_orm::{ConnectOptions, DatabaseConnection};
-use tracing::log::LevelFilter;
+use sea_orm_migration::MigratorTrait;
+use tracing::{info, log::LevelFilter};
 
 #[derive(Clone, Debug)]
 pub struct Database {
     pub conn: DatabaseConnection,
 }
 
-pub async fn initialize() -> Result<Database, DbErr> {
-    let dsn = std::env::var("DATABASE_URL")
-        .map_err(|_| DbErr::Custom("missing DATABASE_URL".to_owned()))?;
-    let mut connect_options = ConnectOptions::new(&dsn);
+/// Connections of the pool, for monitoring.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolStats {
+    /// Open connections, idle or in use.
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
+impl Database {
+    /// Names of the migrations that are known but not applied yet.
+    pub async fn pending_migrations(&self) -> Result<Vec<String>, DbErr> {
+        let pending = Migrator::get_pending_migrations(&self.conn).await?;
+        Ok(pending.iter().map(|m| m.name().to_string()).collect())
+    }
+
+    /// Close the connection pool, waiting for connections in use to be
+    /// returned.
+    pub async fn close(self) -> Result<(), DbErr> {
+        self.conn.close().await
+    }
+
+    /// `None` for connections without a pool, e.g. mocks.
+    pub fn pool_stats(&self) -> Option<PoolStats> {
+        match self.conn {
+            DatabaseConnection::SqlxMySqlPoolConnection(_) => {
+                let pool = self.conn.get_mysql_connection_pool();
+                Some(PoolStats {
+                    size: pool.size(),
+                    idle: pool.num_idle(),
+                    max: pool.options().get_max_connections(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+pub async fn initialize(dsn: &str) -> Result<Database, DbErr> {
+    let mut connect_options = ConnectOptions::new(dsn);
     connect_options
         .acquire_timeout(std::time::Duration::from_secs(15))
         .sqlx_logging(true)
         .sqlx_logging_level(LevelFilter::Debug);
 
     let conn = sea_orm::Database::connect(connect_options).await?;
+    let pending = Migrator::get_pending_migrations(&conn).await?;
+    if !pending.is_empty() {
+        info!("applying {} pending database migration(s)", pending.len());
+        Migrator::up(&conn, None).await?;
+    }
     Ok(Database { conn })
 }
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum AuditLogs {
+    #[sea_orm(iden = "AuditLogs")]
+    Table,
+    Id,
+    ActorId,
+    Action,
+    Target,
+    Before,
+    After,
+    Ip,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLogs::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLogs::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLogs::ActorId).integer().null())
+                    .col(ColumnDef::new(AuditLogs::Action).string_len(64).not_null())
+                    .col(ColumnDef::new(AuditLogs::Target).string_len(128).null())
+                    .col(ColumnDef::new(AuditLogs::Before).json().null())
+                    .col(ColumnDef::new(AuditLogs::After).json().null())
+                    .col(ColumnDef::new(AuditLogs::Ip).string_len(64).null())
+                    .col(
+                        ColumnDef::new(AuditLogs::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_logs_created_at")
+                    .table(AuditLogs::Table)
+                    .col(AuditLogs::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_logs_action")
+                    .table(AuditLogs::Table)
+                    .col(AuditLogs::Action)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLogs::Table).to_owned())
+            .await
+    }
+}
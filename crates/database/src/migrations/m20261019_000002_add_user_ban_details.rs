@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "Users")]
+    Table,
+    BannedUntil,
+    BanReason,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // MySQL has no `ADD COLUMN IF NOT EXISTS`, check first so databases
+        // where the columns were added by hand still migrate.
+        if !manager.has_column("Users", "banned_until").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(
+                            ColumnDef::new(Users::BannedUntil)
+                                .timestamp_with_time_zone()
+                                .null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager.has_column("Users", "ban_reason").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(ColumnDef::new(Users::BanReason).text().null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::BannedUntil)
+                    .drop_column(Users::BanReason)
+                    .to_owned(),
+            )
+            .await
+    }
+}
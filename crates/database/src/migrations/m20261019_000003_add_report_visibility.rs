@@ -0,0 +1,80 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Reports {
+    #[sea_orm(iden = "Reports")]
+    Table,
+    Visibility,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "Users")]
+    Table,
+    DefaultVisibility,
+}
+
+/// `Visibility::Team`, which is how every report behaved before visibility
+/// existed.
+const TEAM: i32 = 1;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // MySQL has no `ADD COLUMN IF NOT EXISTS`, check first so databases
+        // where the columns were added by hand still migrate.
+        if !manager.has_column("Reports", "visibility").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Reports::Table)
+                        .add_column(
+                            ColumnDef::new(Reports::Visibility)
+                                .integer()
+                                .not_null()
+                                .default(TEAM),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager.has_column("Users", "default_visibility").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(
+                            ColumnDef::new(Users::DefaultVisibility)
+                                .integer()
+                                .not_null()
+                                .default(TEAM),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Reports::Table)
+                    .drop_column(Reports::Visibility)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::DefaultVisibility)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,118 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum FeedTokens {
+    #[sea_orm(iden = "FeedTokens")]
+    Table,
+    Id,
+    UserId,
+    Label,
+    TokenHash,
+    CreatedAt,
+    LastUsedAt,
+    RevokedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "Users")]
+    Table,
+    Id,
+    FeedToken,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FeedTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FeedTokens::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(FeedTokens::UserId).integer().not_null())
+                    .col(ColumnDef::new(FeedTokens::Label).string_len(64).not_null())
+                    .col(
+                        ColumnDef::new(FeedTokens::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(FeedTokens::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(FeedTokens::LastUsedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(FeedTokens::RevokedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_feed_tokens_user_id")
+                            .from(FeedTokens::Table, FeedTokens::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Move the existing plaintext tokens over, hashed, so that current
+        // subscriptions keep working. `SHA2` yields lowercase hex, the same
+        // encoding the server uses.
+        if manager.has_column("Users", "feed_token").await? {
+            manager
+                .get_connection()
+                .execute_unprepared(
+                    "INSERT INTO `FeedTokens` (`user_id`, `label`, `token_hash`, `created_at`) \
+                     SELECT `id`, 'default', SHA2(`feed_token`, 256), UTC_TIMESTAMP() \
+                     FROM `Users` WHERE `feed_token` IS NOT NULL AND `feed_token` <> ''",
+                )
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .drop_column(Users::FeedToken)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The plaintext tokens cannot be recovered, users have to create new
+        // ones after a rollback.
+        if !manager.has_column("Users", "feed_token").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(ColumnDef::new(Users::FeedToken).text().null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(FeedTokens::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,28 @@
+//! Schema migrations.
+//!
+//! The original `Users`, `Reports` and `Configs` tables predate the migrator
+//! and are still provisioned by hand, every table or column added since then
+//! is created here. Pending migrations are applied by [`crate::initialize`].
+
+use sea_orm_migration::prelude::*;
+
+mod m20261019_000001_create_audit_logs;
+mod m20261019_000002_add_user_ban_details;
+mod m20261019_000003_add_report_visibility;
+mod m20261019_000004_create_feed_tokens;
+mod m20261019_000005_add_user_role;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261019_000001_create_audit_logs::Migration),
+            Box::new(m20261019_000002_add_user_ban_details::Migration),
+            Box::new(m20261019_000003_add_report_visibility::Migration),
+            Box::new(m20261019_000004_create_feed_tokens::Migration),
+            Box::new(m20261019_000005_add_user_role::Migration),
+        ]
+    }
+}
@@ -0,0 +1,307 @@
+//! Aggregate queries over reports and their authors.
+//!
+//! Lengths are counted in characters of the report content, likes by the
+//! length of the `likes` JSON array. Hidden users are left out of every
+//! aggregate except [`user_reports`], which is asked for one user on purpose.
+//!
+//! Weeks are identified like everywhere else by the `yyyymmdd` date of their
+//! Sunday, [`term_weeks`] lists those of the term.
+
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use sea_orm::{
+    entity::prelude::*,
+    sea_query::{Alias, Func, SimpleExpr},
+    FromQueryResult, JoinType, QueryOrder, QuerySelect,
+};
+use serde::Serialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{report, user};
+
+/// Number of non-hidden users in a direction.
+#[derive(Clone, Debug, FromQueryResult)]
+pub struct DirectionMembers {
+    pub direction: Option<String>,
+    pub members: i64,
+}
+
+/// Reports of non-hidden users, grouped by the direction of their author.
+#[derive(Clone, Debug, FromQueryResult)]
+pub struct DirectionActivity {
+    pub direction: Option<String>,
+    pub reports: i64,
+    /// Total length of the reports.
+    pub length: i64,
+    pub likes: i64,
+}
+
+/// Reports of non-hidden users, grouped by week.
+#[derive(Clone, Debug, FromQueryResult)]
+pub struct WeekActivity {
+    pub week: i32,
+    pub reports: i64,
+    /// Total length of the reports.
+    pub length: i64,
+    pub likes: i64,
+}
+
+#[derive(Clone, Debug, Serialize, FromQueryResult, ToSchema)]
+pub struct LikedReport {
+    pub id: i32,
+    pub author_id: i32,
+    pub author_name: String,
+    pub week: i32,
+    pub likes: i64,
+}
+
+/// Size of a single report.
+#[derive(Clone, Debug, FromQueryResult)]
+pub struct ReportSize {
+    pub week: i32,
+    pub length: i64,
+    pub likes: i64,
+}
+
+fn length() -> SimpleExpr {
+    Func::coalesce([
+        Func::char_length(Expr::col((report::Entity, report::Column::Content))).into(),
+        Expr::val(0).into(),
+    ])
+    .into()
+}
+
+fn likes() -> SimpleExpr {
+    Func::coalesce([
+        Func::cust(Alias::new("JSON_LENGTH"))
+            .arg(Expr::col((report::Entity, report::Column::Likes)))
+            .into(),
+        Expr::val(0).into(),
+    ])
+    .into()
+}
+
+/// `SUM` of an integer expression. MySQL sums into a `DECIMAL`, cast it back.
+fn sum(expr: SimpleExpr) -> SimpleExpr {
+    Func::cast_as(Func::sum(expr), Alias::new("SIGNED")).into()
+}
+
+/// Reports of non-hidden authors, optionally limited to the weeks in
+/// `from..=to`.
+fn visible_reports(from: Option<i32>, to: Option<i32>) -> Select<report::Entity> {
+    let mut query = report::Entity::find()
+        .select_only()
+        .join(JoinType::InnerJoin, report::Relation::Author.def())
+        .filter(user::Column::IsHidden.eq(false));
+    if let Some(from) = from {
+        query = query.filter(report::Column::Week.gte(from));
+    }
+    if let Some(to) = to {
+        query = query.filter(report::Column::Week.lte(to));
+    }
+    query
+}
+
+#[instrument(name = "stats::direction_members", skip_all)]
+pub async fn direction_members<C>(db: &C) -> Result<Vec<DirectionMembers>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    user::Entity::find()
+        .select_only()
+        .column(user::Column::Direction)
+        .column_as(user::Column::Id.count(), "members")
+        .filter(user::Column::IsHidden.eq(false))
+        .group_by(user::Column::Direction)
+        .order_by_asc(user::Column::Direction)
+        .into_model()
+        .all(db)
+        .await
+}
+
+#[instrument(name = "stats::direction_activity", skip_all, fields(?from, ?to))]
+pub async fn direction_activity<C>(
+    db: &C,
+    from: Option<i32>,
+    to: Option<i32>,
+) -> Result<Vec<DirectionActivity>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    visible_reports(from, to)
+        .column(user::Column::Direction)
+        .column_as(report::Column::Id.count(), "reports")
+        .column_as(sum(length()), "length")
+        .column_as(sum(likes()), "likes")
+        .group_by(user::Column::Direction)
+        .order_by_asc(user::Column::Direction)
+        .into_model()
+        .all(db)
+        .await
+}
+
+/// Activity of the weeks in `from..=to` that have reports at all.
+#[instrument(name = "stats::week_activity", skip_all, fields(?from, ?to))]
+pub async fn week_activity<C>(
+    db: &C,
+    from: Option<i32>,
+    to: Option<i32>,
+) -> Result<Vec<WeekActivity>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    visible_reports(from, to)
+        .column(report::Column::Week)
+        .column_as(report::Column::Id.count(), "reports")
+        .column_as(sum(length()), "length")
+        .column_as(sum(likes()), "likes")
+        .group_by(report::Column::Week)
+        .order_by_asc(report::Column::Week)
+        .into_model()
+        .all(db)
+        .await
+}
+
+/// The `limit` reports with the most likes, most recent first among equals.
+#[instrument(name = "stats::most_liked", skip_all, fields(limit))]
+pub async fn most_liked<C>(db: &C, limit: u64) -> Result<Vec<LikedReport>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    visible_reports(None, None)
+        .columns([
+            report::Column::Id,
+            report::Column::AuthorId,
+            report::Column::Week,
+        ])
+        .column_as(user::Column::Name, "author_name")
+        .column_as(likes(), "likes")
+        .filter(Expr::expr(likes()).gt(0))
+        .order_by_desc(likes())
+        .order_by_desc(report::Column::Week)
+        .limit(limit)
+        .into_model()
+        .all(db)
+        .await
+}
+
+/// Size of every report of a user, by week.
+#[instrument(name = "stats::user_reports", skip_all, fields(user_id))]
+pub async fn user_reports<C>(db: &C, user_id: i32) -> Result<Vec<ReportSize>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    report::Entity::find()
+        .select_only()
+        .column(report::Column::Week)
+        .column_as(length(), "length")
+        .column_as(likes(), "likes")
+        .filter(report::Column::AuthorId.eq(user_id))
+        .order_by_asc(report::Column::Week)
+        .into_model()
+        .all(db)
+        .await
+}
+
+/// The date of a `yyyymmdd` week.
+pub fn week_date(week: i32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(
+        week / 10_000,
+        (week / 100 % 100) as u32,
+        (week % 100) as u32,
+    )
+}
+
+/// The `yyyymmdd` week of a date.
+pub fn date_week(date: NaiveDate) -> i32 {
+    date.year() * 10_000 + date.month() as i32 * 100 + date.day() as i32
+}
+
+/// The last week that ended on or before `today`.
+pub fn last_week(today: NaiveDate) -> i32 {
+    let sunday = today - Duration::days(today.weekday().num_days_from_sunday() as i64);
+    date_week(sunday)
+}
+
+/// Every week of the term: the Sundays from `begin` up to the last one on or
+/// before `today`, without the skipped ones. Empty if `begin` is not a date.
+pub fn term_weeks(begin: i32, skip: &[i32], today: NaiveDate) -> Vec<i32> {
+    let Some(begin) = week_date(begin) else {
+        return vec![];
+    };
+    // weeks always start on a Sunday, whatever the configuration says
+    let mut sunday =
+        begin + Duration::days((7 - begin.weekday().num_days_from_sunday() as i64) % 7);
+    let mut weeks = vec![];
+    while sunday <= today {
+        let week = date_week(sunday);
+        if !skip.contains(&week) {
+            weeks.push(week);
+        }
+        sunday += Duration::weeks(1);
+    }
+    weeks
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Streaks {
+    /// Consecutive weeks submitted up to the last week of the term.
+    pub current: usize,
+    pub longest: usize,
+}
+
+/// Runs of consecutive term `weeks` in which a report was `submitted`.
+pub fn streaks(weeks: &[i32], submitted: &BTreeSet<i32>) -> Streaks {
+    let mut streaks = Streaks::default();
+    for week in weeks {
+        if submitted.contains(week) {
+            streaks.current += 1;
+            streaks.longest = streaks.longest.max(streaks.current);
+        } else {
+            streaks.current = 0;
+        }
+    }
+    streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(week: i32) -> NaiveDate {
+        week_date(week).unwrap()
+    }
+
+    #[test]
+    fn term_weeks_are_sundays_without_the_skipped_ones() {
+        let weeks = term_weeks(20260906, &[20260920], date(20261007));
+        assert_eq!(weeks, [20260906, 20260913, 20260927, 20261004]);
+        // a begin that is not a Sunday starts with the following one
+        let weeks = term_weeks(20260902, &[], date(20260913));
+        assert_eq!(weeks, [20260906, 20260913]);
+        assert!(term_weeks(0, &[], date(20260913)).is_empty());
+    }
+
+    #[test]
+    fn last_week_is_the_latest_sunday() {
+        assert_eq!(last_week(date(20261018)), 20261018);
+        assert_eq!(last_week(date(20261024)), 20261018);
+    }
+
+    #[test]
+    fn streaks_count_consecutive_weeks() {
+        let weeks = [1, 2, 3, 4, 5, 6];
+        let submitted = BTreeSet::from([1, 2, 3, 5, 6]);
+        assert_eq!(
+            streaks(&weeks, &submitted),
+            Streaks {
+                current: 2,
+                longest: 3
+            }
+        );
+        let submitted = BTreeSet::from([1, 2]);
+        assert_eq!(streaks(&weeks, &submitted).current, 0);
+    }
+}
@@ -0,0 +1,198 @@
+//! Audit trail of administrative and security-relevant actions.
+//!
+//! Handlers take an [`Auditor`] extractor, which already knows who is acting
+//! and from where, and call [`Auditor::record`] once the action succeeded.
+//! Entries are kept for a configurable number of days, see
+//! [`spawn_retention`].
+
+use std::{net::IpAddr, time::Duration};
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info};
+use wr_database::{audit_log, user, Database};
+
+use crate::middleware::forwarded::ClientIp;
+
+pub struct Auditor {
+    db: Database,
+    actor_id: Option<i32>,
+    ip: Option<IpAddr>,
+}
+
+impl<S> FromRequestParts<S> for Auditor
+where
+    Database: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state).await?;
+        Ok(Auditor {
+            db: Database::from_ref(state),
+            actor_id: parts.extensions.get::<user::Model>().map(|u| u.id),
+            ip,
+        })
+    }
+}
+
+impl Auditor {
+    /// Write an audit entry. A failure to write is logged but never fails the
+    /// request, the action itself has already happened at this point.
+    pub async fn record(
+        &self,
+        action: &str,
+        target: Option<String>,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) {
+        info!(
+            target: "audit",
+            actor_id = ?self.actor_id,
+            action,
+            target = ?target,
+            "audit event"
+        );
+        let entry = audit_log::Model {
+            actor_id: self.actor_id,
+            action: action.to_string(),
+            target,
+            before,
+            after,
+            ip: self.ip.map(|ip| ip.to_string()),
+            created_at: Utc::now(),
+            ..Default::default()
+        };
+        if let Err(e) = audit_log::create(&self.db.conn, entry).await {
+            error!(action, "failed to write audit log: {e}");
+        }
+    }
+
+    /// Record a modification, storing only the top-level fields that differ
+    /// between `before` and `after`.
+    pub async fn record_change<T: Serialize>(
+        &self,
+        action: &str,
+        target: Option<String>,
+        before: &T,
+        after: &T,
+    ) {
+        let (old, new) = diff(before, after);
+        self.record(action, target, Some(old), Some(new)).await;
+    }
+}
+
+/// Collect the top-level fields that differ between two serializable values,
+/// returned as a pair of (old, new) JSON objects.
+fn diff<T: Serialize>(before: &T, after: &T) -> (Value, Value) {
+    let (Ok(Value::Object(before)), Ok(Value::Object(after))) =
+        (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return (Value::Null, Value::Null);
+    };
+    let mut old = Map::new();
+    let mut new = Map::new();
+    for (key, value) in after {
+        let prev = before.get(&key).cloned().unwrap_or_default();
+        if prev != value {
+            old.insert(key.clone(), prev);
+            new.insert(key, value);
+        }
+    }
+    (old.into(), new.into())
+}
+
+/// Purge entries older than `retention_days` once a day until `shutdown` is
+/// cancelled. A retention of `0` keeps entries forever.
+pub fn spawn_retention(
+    tasks: &TaskTracker,
+    shutdown: CancellationToken,
+    db: Database,
+    retention_days: u32,
+) {
+    if retention_days == 0 {
+        info!("audit log retention disabled, entries are kept forever");
+        return;
+    }
+    tasks.spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            let edge = Utc::now() - chrono::Duration::days(retention_days as i64);
+            match audit_log::delete_before(&db.conn, edge).await {
+                Ok(0) => {}
+                Ok(n) => info!("purged {n} audit log entries older than {retention_days} days"),
+                Err(e) => error!("failed to purge audit log: {e}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+    use wr_database::feed_token;
+
+    use super::*;
+
+    #[test]
+    fn diff_reports_only_changed_fields() {
+        let before = user::Model {
+            id: 7,
+            name: "alice".to_string(),
+            direction: Some("web".to_string()),
+            ..Default::default()
+        };
+        let until = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let after = user::Model {
+            is_banned: true,
+            banned_until: Some(until),
+            ban_reason: Some("spam".to_string()),
+            ..before.clone()
+        };
+        let (old, new) = diff(&before, &after);
+        assert_eq!(
+            old,
+            json!({ "is_banned": false, "banned_until": null, "ban_reason": null })
+        );
+        assert_eq!(
+            new,
+            json!({ "is_banned": true, "banned_until": until.timestamp(), "ban_reason": "spam" })
+        );
+        assert_eq!(diff(&before, &before), (json!({}), json!({})));
+    }
+
+    #[test]
+    fn diff_never_contains_token_hashes() {
+        let before = feed_token::Model {
+            id: 1,
+            label: "default".to_string(),
+            token_hash: "a".repeat(64),
+            ..Default::default()
+        };
+        let after = feed_token::Model {
+            token_hash: "b".repeat(64),
+            revoked_at: Some(Utc::now()),
+            ..before.clone()
+        };
+        let (old, new) = diff(&before, &after);
+        assert_eq!(old, json!({ "revoked_at": null }));
+        assert!(new.get("revoked_at").is_some());
+        for value in [old, new] {
+            let text = value.to_string();
+            assert!(!text.contains("token_hash") && !text.contains(&"a".repeat(64)));
+            assert!(!text.contains(&"b".repeat(64)));
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Live updates for the web client.
+//!
+//! Handlers publish an [`Event`] on the in-process [`EventBus`] once the
+//! change is stored, `GET /api/events` streams them to every subscriber that
+//! may read the report they are about, see [`Event::visible_to`].
+
+use std::sync::Arc;
+
+use chrono::{serde::ts_seconds, DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use wr_database::{
+    report::{self, Audience, Visibility},
+    user,
+};
+
+/// Events kept for slow subscribers before they start missing some.
+const CAPACITY: usize = 256;
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<Event>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self { sender }
+    }
+
+    /// Send `event` to the current subscribers, if there are any.
+    pub fn publish(&self, event: Event) {
+        // an error only means that nobody is listening
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.sender.subscribe()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Event {
+    /// A report was submitted for the first time this week.
+    ReportSubmitted(ReportEvent),
+    /// A report was edited.
+    ReportUpdated(ReportEvent),
+    /// A report was liked or unliked.
+    ReportLiked(LikeEvent),
+    /// Someone submitted their first report of the week.
+    StatusChanged(StatusEvent),
+}
+
+impl Event {
+    /// Name of the event in the stream.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ReportSubmitted(_) => "report.submitted",
+            Event::ReportUpdated(_) => "report.updated",
+            Event::ReportLiked(_) => "report.liked",
+            Event::StatusChanged(_) => "status.changed",
+        }
+    }
+
+    /// Whether a subscriber reading reports as `audience` gets the event.
+    /// Report events follow the visibility of the report, the status is
+    /// readable by every member.
+    pub fn visible_to(&self, audience: &Audience) -> bool {
+        match self {
+            Event::ReportSubmitted(e) | Event::ReportUpdated(e) => {
+                audience.can_read(&e.author, e.visibility)
+            }
+            Event::ReportLiked(e) => audience.can_read(&e.author, e.visibility),
+            Event::StatusChanged(_) => true,
+        }
+    }
+}
+
+/// A report without its content, which the client fetches if it wants to.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ReportEvent {
+    pub id: i32,
+    pub author_id: i32,
+    pub author_name: String,
+    pub week: i32,
+    pub visibility: Visibility,
+    #[serde(with = "ts_seconds")]
+    #[schema(value_type = i64)]
+    pub date: DateTime<Utc>,
+    #[serde(skip)]
+    author: user::Model,
+}
+
+impl ReportEvent {
+    pub fn new(report: &report::Model, author: &user::Model) -> Self {
+        Self {
+            id: report.id,
+            author_id: report.author_id,
+            author_name: author.name.clone(),
+            week: report.week,
+            visibility: report.visibility,
+            date: report.date,
+            author: author.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct LikeEvent {
+    pub report_id: i32,
+    pub author_id: i32,
+    /// Name of the user who liked or unliked the report.
+    pub user: String,
+    /// `false` when the like was taken back.
+    pub liked: bool,
+    /// Names of the users who like the report now.
+    pub likes: Vec<String>,
+    #[serde(skip)]
+    visibility: Visibility,
+    #[serde(skip)]
+    author: user::Model,
+}
+
+impl LikeEvent {
+    pub fn new(
+        report: &report::ExModel,
+        author: &user::Model,
+        user: &user::Model,
+        liked: bool,
+        likes: Vec<String>,
+    ) -> Self {
+        Self {
+            report_id: report.id,
+            author_id: report.author_id,
+            user: user.name.clone(),
+            liked,
+            likes,
+            visibility: report.visibility,
+            author: author.clone(),
+        }
+    }
+}
+
+/// Who has and has not submitted a report this week, like `GET /api/status`.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct StatusEvent {
+    pub submitted: Vec<String>,
+    pub pending: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use wr_database::report::PrivateAccess;
+
+    use super::*;
+
+    fn author(id: i32, direction: &str, is_hidden: bool) -> user::Model {
+        user::Model {
+            id,
+            name: format!("user{id}"),
+            direction: Some(direction.to_string()),
+            is_hidden,
+            ..Default::default()
+        }
+    }
+
+    fn submitted(author: &user::Model, visibility: Visibility) -> Event {
+        let report = report::Model {
+            id: 1,
+            author_id: author.id,
+            week: 20261018,
+            visibility,
+            ..Default::default()
+        };
+        Event::ReportSubmitted(ReportEvent::new(&report, author))
+    }
+
+    fn member(user_id: i32, private: PrivateAccess) -> Audience {
+        Audience::Member {
+            user_id,
+            private,
+            include_hidden: false,
+        }
+    }
+
+    #[test]
+    fn private_reports_reach_the_author_and_their_mentors() {
+        let event = submitted(&author(1, "web", false), Visibility::Private);
+        assert!(event.visible_to(&member(1, PrivateAccess::None)));
+        assert!(event.visible_to(&member(2, PrivateAccess::Direction("web".into()))));
+        assert!(!event.visible_to(&member(2, PrivateAccess::Direction("pwn".into()))));
+        assert!(!event.visible_to(&member(2, PrivateAccess::None)));
+    }
+
+    #[test]
+    fn hidden_authors_are_filtered() {
+        let event = submitted(&author(1, "web", true), Visibility::Team);
+        assert!(!event.visible_to(&member(2, PrivateAccess::All)));
+        assert!(event.visible_to(&Audience::Member {
+            user_id: 2,
+            private: PrivateAccess::None,
+            include_hidden: true,
+        }));
+    }
+
+    #[test]
+    fn events_serialize_without_the_author() {
+        let event = submitted(&author(1, "web", false), Visibility::Team);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(event.name(), "report.submitted");
+        assert_eq!(json["author_name"], "user1");
+        assert!(json.get("author").is_none());
+    }
+}
@@ -1,13 +1,25 @@
+mod audit;
+mod events;
+mod listener;
 mod logging;
+mod metrics;
 mod middleware;
+#[cfg(feature = "otel")]
+mod otel;
+mod policy;
 mod routes;
+mod settings;
+mod tls;
 mod traits;
-use std::{net::SocketAddr, process};
+use std::{process, sync::Arc};
 
 use colored::Colorize;
 use rustls::crypto;
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing::{error, info, warn};
 use traits::GlobalState;
+pub use settings::{Overrides, Settings};
 pub use traits::ResponseError;
 
 /// Show greet information.
@@ -32,9 +44,52 @@ pub fn greet() {
     );
 }
 
-pub async fn up() -> anyhow::Result<()> {
-    let console_guard = logging::initialize().await?;
+/// The OpenAPI document of the API, pretty printed.
+pub fn openapi() -> String {
+    routes::openapi::spec()
+        .to_pretty_json()
+        .expect("OpenAPI document is serializable")
+}
+
+/// Cancel `shutdown` on the first SIGINT or SIGTERM.
+async fn cancel_on_signal(shutdown: CancellationToken) {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("cannot listen for SIGINT: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("cannot listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("received SIGINT, shutting down"),
+        _ = terminate => info!("received SIGTERM, shutting down"),
+    }
+    shutdown.cancel();
+}
+
+pub async fn up(overrides: Overrides) -> anyhow::Result<()> {
+    let settings = Arc::new(Settings::load(overrides)?);
+    let log_guards = logging::initialize(&settings.log).await?;
     info!(">> Server initialization started <<");
+    #[cfg(not(feature = "otel"))]
+    if settings.log.otel.is_some() {
+        warn!("built without the `otel` feature, spans are not exported");
+    }
 
     match crypto::aws_lc_rs::default_provider().install_default() {
         Ok(_) => info!("using `AWS Libcrypto` as default crypto backend."),
@@ -53,10 +108,24 @@ pub async fn up() -> anyhow::Result<()> {
         }
     }
     info!("Loading module: < Database >");
-    let db = wr_database::initialize().await?;
+    let db = wr_database::initialize(&settings.database.url).await?;
+
+    // Background tasks stop when `shutdown` is cancelled, `tasks` waits for
+    // them to do so.
+    let shutdown = CancellationToken::new();
+    let tasks = TaskTracker::new();
+    audit::spawn_retention(
+        &tasks,
+        shutdown.clone(),
+        db.clone(),
+        settings.audit.retention_days,
+    );
 
     let state = GlobalState {
-        db,
+        db: db.clone(),
+        settings: settings.clone(),
+        events: events::EventBus::new(),
+        shutdown: shutdown.clone(),
         version: format!(
             "{}-{}",
             env!("CARGO_PKG_VERSION"),
@@ -67,6 +136,20 @@ pub async fn up() -> anyhow::Result<()> {
             .to_uppercase()
         ),
     };
+    if let Some(listen) = settings.metrics.listen {
+        info!("Loading module: < Metrics >");
+        let handle = metrics::install()?;
+        let listener = tokio::net::TcpListener::bind(listen).await?;
+        info!("Metrics available at [ http://{listen}/metrics ]");
+        let router = metrics::router(state.db.clone(), handle);
+        let stopped = shutdown.clone().cancelled_owned();
+        tasks.spawn(async move {
+            let server = axum::serve(listener, router).with_graceful_shutdown(stopped);
+            if let Err(e) = server.await {
+                error!("metrics listener failed: {e}");
+            }
+        });
+    }
     info!("Modules loaded, constructing router...");
 
     let router = routes::initialize(state).await?;
@@ -76,23 +159,83 @@ pub async fn up() -> anyhow::Result<()> {
 
     info!("Starting server...");
 
-    let addr_str = format!(
-        "{}:{}",
-        std::env::var("WR_HOST").unwrap(),
-        std::env::var("WR_PORT").unwrap(),
-    );
+    let listeners = listener::bind(&settings.server.listen, settings.server.unix_socket_mode)?;
+    tokio::spawn(cancel_on_signal(shutdown.clone()));
+    let tls_config = match &settings.server.tls {
+        None => None,
+        Some(tls) => {
+            let resolver = Arc::new(tls::CertResolver::new(tls)?);
+            tls::spawn_reload(&tasks, shutdown.clone(), resolver.clone());
+            if let Some(from) = tls.redirect_from {
+                match listeners.iter().find_map(listener::Bound::port) {
+                    Some(port) => {
+                        let listener = tokio::net::TcpListener::bind(from).await?;
+                        info!("Redirecting [ http://{from} ] to HTTPS");
+                        let router = tls::redirect_router(port);
+                        let stopped = shutdown.clone().cancelled_owned();
+                        tasks.spawn(async move {
+                            let server =
+                                axum::serve(listener, router).with_graceful_shutdown(stopped);
+                            if let Err(e) = server.await {
+                                error!("redirect listener failed: {e}");
+                            }
+                        });
+                    }
+                    None => warn!("no TCP listener to redirect [ http://{from} ] to"),
+                }
+            }
+            Some(tls::server_config(resolver))
+        }
+    };
+    let mut servers = Vec::with_capacity(listeners.len());
+    for bound in listeners {
+        servers.push(bound.serve(router.clone(), tls_config.clone(), shutdown.clone())?);
+    }
+    let server = futures::future::try_join_all(servers);
+    listener::notify_ready();
 
-    let addr = tokio::net::TcpListener::bind(addr_str.clone())
+    // Requests and background tasks share one drain deadline, counted from
+    // the moment shutdown starts.
+    let drain_timeout = settings.server.drain_timeout;
+    let stopping = async {
+        shutdown.cancelled().await;
+        listener::notify_stopping();
+        Instant::now() + drain_timeout
+    };
+    let mut server = std::pin::pin!(server);
+    let stopped = tokio::select! {
+        biased;
+        deadline = stopping => Some(deadline),
+        result = &mut server => {
+            result?;
+            None
+        }
+    };
+    let deadline = match stopped {
+        Some(deadline) => {
+            match tokio::time::timeout_at(deadline, server).await {
+                Ok(result) => {
+                    result?;
+                }
+                Err(_) => warn!("requests still running after {drain_timeout:?}, dropping them"),
+            }
+            deadline
+        }
+        None => Instant::now() + drain_timeout,
+    };
+
+    tasks.close();
+    if tokio::time::timeout_at(deadline, tasks.wait())
         .await
-        .expect("Failed to bind server address");
-    info!("Server started at [ {} ]", addr_str);
-    axum::serve(
-        addr,
-        router.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await
-    .expect("Failed to start server.");
-
-    drop(console_guard);
+        .is_err()
+    {
+        warn!("background tasks did not stop within {drain_timeout:?}");
+    }
+    if let Err(e) = db.close().await {
+        error!("failed to close the database pool: {e}");
+    }
+    info!("Server stopped.");
+
+    drop(log_guards);
     Ok(())
 }
@@ -0,0 +1,346 @@
+//! Where the server accepts connections.
+//!
+//! The server listens on any number of TCP addresses and Unix domain sockets
+//! (`unix:/run/weekly-report/wr.sock`), the latter for a reverse proxy on the
+//! same machine. Started by systemd socket activation, the sockets passed in
+//! `LISTEN_FDS` are used instead of the configured addresses. Readiness and
+//! shutdown are reported to systemd through `sd_notify` when it asks for it.
+
+use std::{fmt, future::IntoFuture, io, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc};
+
+#[cfg(unix)]
+use axum::{extract::connect_info::Connected, serve::IncomingStream};
+use axum::{serve::ListenerExt, Router};
+use futures::{future::BoxFuture, FutureExt};
+use listenfd::ListenFd;
+use rustls::ServerConfig;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::tls::TlsListener;
+
+/// An address from `server.listen`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenAddr {
+    /// `host:port`, the host may be a name.
+    Tcp(String),
+    /// `unix:<path>`
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(format!("`{s}` has no socket path"));
+            }
+            return Ok(ListenAddr::Unix(path.into()));
+        }
+        match s.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                Ok(ListenAddr::Tcp(s.to_string()))
+            }
+            _ => Err(format!(
+                "`{s}` must be an address like 127.0.0.1:8080 or unix:/path/to.sock"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => f.write_str(addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ListenError {
+    #[error("cannot listen on {0}: {1}")]
+    Bind(ListenAddr, io::Error),
+    #[error("cannot set the permissions of {0}: {1}")]
+    Permissions(PathBuf, io::Error),
+    #[error("unusable socket {0} passed by systemd: {1}")]
+    Inherited(usize, io::Error),
+    #[cfg(not(unix))]
+    #[error("cannot listen on {0}: Unix domain sockets are not supported on this platform")]
+    Unsupported(ListenAddr),
+}
+
+/// Connection info of requests over a Unix domain socket. Only local
+/// processes that the socket permissions let in can connect, so the peer is
+/// trusted like a proxy, see [`crate::middleware::forwarded::resolve`].
+#[derive(Clone, Copy, Debug)]
+pub struct UnixPeer;
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for UnixPeer {
+    fn connect_info(_: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        UnixPeer
+    }
+}
+
+/// A socket ready to accept connections.
+pub enum Bound {
+    Tcp(TcpListener),
+    /// The path is set when the socket file is ours to remove on shutdown.
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, Option<PathBuf>),
+}
+
+/// Bind every address, or take the sockets passed by systemd if there are
+/// any. `unix_mode` is applied to the socket files that are created.
+pub fn bind(addrs: &[ListenAddr], unix_mode: Option<u32>) -> Result<Vec<Bound>, ListenError> {
+    let inherited = inherited()?;
+    if !inherited.is_empty() {
+        info!(
+            "using {} socket(s) passed by systemd, server.listen is ignored",
+            inherited.len()
+        );
+        return Ok(inherited);
+    }
+    addrs.iter().map(|addr| bind_one(addr, unix_mode)).collect()
+}
+
+fn bind_one(addr: &ListenAddr, unix_mode: Option<u32>) -> Result<Bound, ListenError> {
+    match addr {
+        ListenAddr::Tcp(host_port) => {
+            let bind = || {
+                let listener = std::net::TcpListener::bind(host_port.as_str())?;
+                listener.set_nonblocking(true)?;
+                TcpListener::from_std(listener)
+            };
+            bind()
+                .map(Bound::Tcp)
+                .map_err(|e| ListenError::Bind(addr.clone(), e))
+        }
+        #[cfg(unix)]
+        ListenAddr::Unix(path) => {
+            let listener = bind_unix(path).map_err(|e| ListenError::Bind(addr.clone(), e))?;
+            if let Some(mode) = unix_mode {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                    .map_err(|e| ListenError::Permissions(path.clone(), e))?;
+            }
+            Ok(Bound::Unix(listener, Some(path.clone())))
+        }
+        #[cfg(not(unix))]
+        ListenAddr::Unix(_) => {
+            let _ = unix_mode;
+            Err(ListenError::Unsupported(addr.clone()))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path) -> io::Result<tokio::net::UnixListener> {
+    use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+
+    // A socket file left behind by a process that was killed would fail the
+    // bind, but one that still accepts connections belongs to a running
+    // server.
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::ErrorKind::AddrInUse.into());
+            }
+            std::fs::remove_file(path)?;
+        }
+    }
+    tokio::net::UnixListener::bind(path)
+}
+
+/// Sockets passed by systemd, see `sd_listen_fds(3)`.
+fn inherited() -> Result<Vec<Bound>, ListenError> {
+    let mut fds = ListenFd::from_env();
+    let mut bound = Vec::new();
+    for index in 0..fds.len() {
+        let err = |e| ListenError::Inherited(index, e);
+        let tcp = match fds.take_tcp_listener(index) {
+            Ok(listener) => listener,
+            #[cfg(unix)]
+            Err(_) => {
+                let listener = fds.take_unix_listener(index).map_err(err)?;
+                if let Some(listener) = listener {
+                    listener.set_nonblocking(true).map_err(err)?;
+                    let listener = tokio::net::UnixListener::from_std(listener).map_err(err)?;
+                    // systemd owns the socket file
+                    bound.push(Bound::Unix(listener, None));
+                }
+                continue;
+            }
+            #[cfg(not(unix))]
+            Err(e) => return Err(err(e)),
+        };
+        if let Some(listener) = tcp {
+            listener.set_nonblocking(true).map_err(err)?;
+            bound.push(Bound::Tcp(TcpListener::from_std(listener).map_err(err)?));
+        }
+    }
+    Ok(bound)
+}
+
+impl Bound {
+    /// The port of a TCP socket.
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Bound::Tcp(listener) => listener.local_addr().ok().map(|addr| addr.port()),
+            #[cfg(unix)]
+            Bound::Unix(..) => None,
+        }
+    }
+
+    /// Serve `router` until `shutdown` is cancelled. TCP sockets speak HTTPS
+    /// when `tls` is given, Unix sockets always speak plain HTTP.
+    pub fn serve(
+        self,
+        router: Router,
+        tls: Option<Arc<ServerConfig>>,
+        shutdown: CancellationToken,
+    ) -> io::Result<BoxFuture<'static, io::Result<()>>> {
+        let stopped = shutdown.cancelled_owned();
+        let server = match self {
+            Bound::Tcp(listener) => {
+                let addr = listener.local_addr()?;
+                let app = router.into_make_service_with_connect_info::<SocketAddr>();
+                match tls {
+                    None => {
+                        info!("Server started at [ http://{addr} ]");
+                        axum::serve(listener, app)
+                            .with_graceful_shutdown(stopped)
+                            .into_future()
+                            .boxed()
+                    }
+                    Some(config) => {
+                        let listener = TlsListener::new(listener, config)?
+                            // `tap_io` is also what provides `ConnectInfo<SocketAddr>`
+                            .tap_io(|stream| {
+                                let _ = stream.get_ref().0.set_nodelay(true);
+                            });
+                        info!("Server started at [ https://{addr} ]");
+                        axum::serve(listener, app)
+                            .with_graceful_shutdown(stopped)
+                            .into_future()
+                            .boxed()
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Bound::Unix(listener, path) => {
+                match listener.local_addr()?.as_pathname() {
+                    Some(name) => info!("Server started at [ unix:{} ]", name.display()),
+                    None => info!("Server started at [ unix socket ]"),
+                }
+                let app = router.into_make_service_with_connect_info::<UnixPeer>();
+                let server = axum::serve(listener, app).with_graceful_shutdown(stopped);
+                async move {
+                    let result = server.await;
+                    if let Some(path) = path {
+                        if let Err(e) = std::fs::remove_file(&path) {
+                            debug!("cannot remove {}: {e}", path.display());
+                        }
+                    }
+                    result
+                }
+                .boxed()
+            }
+        };
+        Ok(server)
+    }
+}
+
+/// Tell systemd that the service accepts connections, for `Type=notify`.
+pub fn notify_ready() {
+    #[cfg(unix)]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        debug!("sd_notify READY failed: {e}");
+    }
+}
+
+/// Tell systemd that the service is shutting down.
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]) {
+        debug!("sd_notify STOPPING failed: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listen_addresses() {
+        assert_eq!(
+            "0.0.0.0:8080".parse(),
+            Ok(ListenAddr::Tcp("0.0.0.0:8080".into()))
+        );
+        assert_eq!(
+            "[::1]:8080".parse(),
+            Ok(ListenAddr::Tcp("[::1]:8080".into()))
+        );
+        assert_eq!(
+            " unix:/run/wr.sock".parse(),
+            Ok(ListenAddr::Unix("/run/wr.sock".into()))
+        );
+        for bad in ["8080", "localhost", ":80", "host:http", "unix:"] {
+            assert!(bad.parse::<ListenAddr>().is_err(), "{bad}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_over_unix_sockets() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use axum::{extract::ConnectInfo, routing::get};
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::UnixStream,
+        };
+
+        let dir = std::env::temp_dir().join(format!("wr-listen-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wr.sock");
+        // a leftover of a killed process
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let addr = ListenAddr::Unix(path.clone());
+        let bound = bind_one(&addr, Some(0o660)).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        assert!(matches!(
+            bind_one(&addr, None),
+            Err(ListenError::Bind(_, e)) if e.kind() == io::ErrorKind::AddrInUse
+        ));
+
+        let router = Router::new().route(
+            "/",
+            get(|ConnectInfo(UnixPeer): ConnectInfo<UnixPeer>| async { "over unix" }),
+        );
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(bound.serve(router, None, shutdown.clone()).unwrap());
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("over unix"), "{response}");
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,37 +1,215 @@
 //! This module setup the logger of `tracing`.
 //!
-//! In the initialization stage, the log writer will be guarded by their file
-//! descriptor, the lifetime of the file descriptor is the same as the log
-//! writer. You should keep the file descriptor until application exit.
+//! Logs always go to the console, and optionally to rolling files as well.
+//! Both writers are non-blocking, their buffers are flushed by the
+//! [`LogGuards`] returned from [`initialize`]. Keep them until application
+//! exit, dropping them early loses the last lines.
+//!
+//! Built with the `otel` feature, spans are also exported to an OpenTelemetry
+//! collector when [`OtelSettings`] are given, see [`crate::otel`].
+
+use std::{path::PathBuf, str::FromStr};
 
 use thiserror::Error;
-use tracing_appender::{non_blocking, non_blocking::WorkerGuard, rolling};
-use tracing_subscriber::{fmt::Layer, prelude::*, EnvFilter};
+use tracing::Subscriber;
+use tracing_appender::{
+    non_blocking,
+    non_blocking::WorkerGuard,
+    rolling::{self, RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{fmt::Layer as FmtLayer, prelude::*, registry::LookupSpan, EnvFilter};
 
 #[derive(Error, Debug)]
 pub enum LoggerError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("file logger init error")]
+    #[error("file logger init error: {0}")]
     FileLoggerInitError(#[from] rolling::InitError),
+    #[error("invalid log filter: {0}")]
+    Filter(#[from] tracing_subscriber::filter::ParseError),
+    #[cfg(feature = "otel")]
+    #[error("otlp exporter init error: {0}")]
+    Otlp(#[from] opentelemetry_otlp::ExporterBuildError),
 }
 
-/// Initialize the logger.
-pub async fn initialize() -> Result<WorkerGuard, LoggerError> {
-    let (non_blocking_console, _console_guard) = non_blocking(std::io::stdout());
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Plain,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "plain" => Ok(LogFormat::Plain),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("`{s}` is not a log format, use plain or json")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "never" => Ok(LogRotation::Never),
+            _ => Err(format!(
+                "`{s}` is not a log rotation, use hourly, daily or never"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    /// Protobuf over HTTP.
+    Http,
+}
+
+impl FromStr for OtlpProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "grpc" => Ok(OtlpProtocol::Grpc),
+            "http" | "http/protobuf" => Ok(OtlpProtocol::Http),
+            _ => Err(format!(
+                "`{s}` is not an OTLP protocol, use grpc or http/protobuf"
+            )),
+        }
+    }
+}
 
-    let console_log_layer = Layer::new()
-        .with_writer(non_blocking_console)
-        .with_ansi(true)
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    /// `RUST_LOG` style directives, e.g. `info,wr_server=debug`.
+    pub filter: String,
+    pub format: LogFormat,
+    pub file: Option<FileLogSettings>,
+    pub otel: Option<OtelSettings>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileLogSettings {
+    pub dir: PathBuf,
+    /// File names are `<prefix>.<date>.log`.
+    pub prefix: String,
+    pub rotation: LogRotation,
+    /// Rotated files to keep, `None` keeps them all.
+    pub max_files: Option<usize>,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone)]
+pub struct OtelSettings {
+    /// Base URL of the collector, e.g. `http://localhost:4317`.
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    pub service_name: String,
+}
+
+/// Flush the log writers and pending spans when dropped.
+#[must_use = "logs are lost once the guards are dropped"]
+pub struct LogGuards {
+    _writers: Vec<WorkerGuard>,
+    #[cfg(feature = "otel")]
+    tracer: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(feature = "otel")]
+impl Drop for LogGuards {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.tracer.take().map(|tracer| tracer.shutdown()) {
+            eprintln!("failed to flush spans: {e}");
+        }
+    }
+}
+
+pub(crate) type BoxedLayer<S> = Box<dyn tracing_subscriber::Layer<S> + Send + Sync>;
+
+fn layer<S, W>(writer: W, format: LogFormat, ansi: bool) -> BoxedLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = FmtLayer::new()
+        .with_writer(writer)
+        .with_ansi(ansi)
         .with_target(true)
         .with_level(true)
         .with_thread_ids(false)
         .with_thread_names(false);
+    match format {
+        LogFormat::Plain => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Initialize the logger.
+pub async fn initialize(settings: &LogSettings) -> Result<LogGuards, LoggerError> {
+    let filter = EnvFilter::try_new(&settings.filter)?;
+    let mut guards = Vec::new();
+    let mut layers = Vec::new();
+
+    let (non_blocking_console, console_guard) = non_blocking(std::io::stdout());
+    guards.push(console_guard);
+    layers.push(layer(non_blocking_console, settings.format, true));
+
+    if let Some(file) = &settings.file {
+        let rotation = match file.rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        };
+        let mut builder = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(&file.prefix)
+            .filename_suffix("log");
+        if let Some(max_files) = file.max_files {
+            builder = builder.max_log_files(max_files);
+        }
+        std::fs::create_dir_all(&file.dir)?;
+        let (non_blocking_file, file_guard) = non_blocking(builder.build(&file.dir)?);
+        guards.push(file_guard);
+        layers.push(layer(non_blocking_file, file.format, false));
+    }
+
+    #[cfg(feature = "otel")]
+    let tracer = match &settings.otel {
+        Some(otel) => {
+            let provider = crate::otel::provider(otel)?;
+            layers.push(crate::otel::layer(&provider));
+            Some(provider)
+        }
+        None => None,
+    };
 
     tracing_subscriber::registry()
-        .with(EnvFilter::new("info"))
-        .with(console_log_layer)
+        .with(layers)
+        .with(filter)
         .init();
 
-    Ok(_console_guard)
+    Ok(LogGuards {
+        _writers: guards,
+        #[cfg(feature = "otel")]
+        tracer,
+    })
 }
@@ -2,7 +2,7 @@ use std::process::exit;
 
 use clap::{Parser, Subcommand};
 use colored::Colorize;
-use wr_server::{greet, up};
+use wr_server::{greet, openapi, up, Overrides};
 
 /// Clap arg definition.
 #[derive(Parser, Debug)]
@@ -29,19 +29,27 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Run the server.
-    Up,
+    Up(Overrides),
+    /// Print the OpenAPI document of the API.
+    Openapi,
 }
 
 /// Server entry.
 #[tokio::main]
 async fn main() {
-    greet();
     // Parse command line arguments
     let args: Args = Args::parse();
-    match match args.command {
-        Some(Commands::Up) => up().await,
-        None => up().await,
-    } {
+    let overrides = match args.command {
+        Some(Commands::Up(overrides)) => overrides,
+        Some(Commands::Openapi) => {
+            // stdout carries the document only, so no greeting
+            println!("{}", openapi());
+            return;
+        }
+        None => Overrides::default(),
+    };
+    greet();
+    match up(overrides).await {
         Ok(_) => {}
         Err(e) => {
             eprintln!("{}: {e}", "Failed to start server".red().bold());
@@ -0,0 +1,93 @@
+//! Prometheus metrics, served on their own listener so that they are not
+//! reachable through the public site.
+//!
+//! HTTP metrics are recorded by [`track`] for every API request, labelled by
+//! the matched route rather than the raw path to keep the label set small.
+//! Database pool and submission gauges are refreshed on each scrape.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tracing::error;
+use wr_database::Database;
+
+use crate::routes;
+
+const REQUEST_DURATION: &str = "http_request_duration_seconds";
+
+/// Latency buckets in seconds, from a cached read to a slow import.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Install the global recorder. Until this is called every metric is a no-op.
+pub fn install() -> anyhow::Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(REQUEST_DURATION.to_string()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+    Ok(handle)
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    db: Database,
+    handle: PrometheusHandle,
+}
+
+pub fn router(db: Database, handle: PrometheusHandle) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(MetricsState { db, handle })
+}
+
+async fn render(State(state): State<MetricsState>) -> impl IntoResponse {
+    if let Some(pool) = state.db.pool_stats() {
+        gauge!("db_pool_connections").set(pool.size as f64);
+        gauge!("db_pool_idle_connections").set(pool.idle as f64);
+        gauge!("db_pool_max_connections").set(pool.max as f64);
+    }
+    match routes::week_status(&state.db).await {
+        Ok(status) => {
+            gauge!("reports_submitted_this_week").set(status.submitted.len() as f64);
+            gauge!("reports_pending_this_week").set(status.pending.len() as f64);
+        }
+        Err(e) => error!("failed to refresh submission gauges: {e}"),
+    }
+    state.handle.run_upkeep();
+    (
+        [("content-type", "text/plain; version=0.0.4")],
+        state.handle.render(),
+    )
+}
+
+/// Count requests and measure their latency by method, matched route and
+/// status.
+pub async fn track(req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let resp = next.run(req).await;
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", resp.status().as_u16().to_string()),
+    ];
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!(REQUEST_DURATION, &labels).record(start.elapsed().as_secs_f64());
+    resp
+}
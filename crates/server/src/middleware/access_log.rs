@@ -0,0 +1,62 @@
+//! The per request span and the access log written when a response is sent.
+//!
+//! Everything logged while handling a request is inside the `http` span, so
+//! log lines carry the request id that clients see in `x-request-id` and in
+//! error bodies. The user id is filled in once the user is known.
+
+use std::{net::IpAddr, time::Duration};
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request},
+    http::header::CONTENT_LENGTH,
+    response::Response,
+};
+use tracing::{field::Empty, info, info_span, Span};
+use wr_database::user;
+
+use super::{forwarded, request_id};
+
+/// Span of a request, used as `TraceLayer::make_span_with`.
+pub fn make_span(req: &Request) -> Span {
+    let ip = forwarded::get_client_ip(req).unwrap_or(IpAddr::V4([0, 0, 0, 0].into()));
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+    let span = info_span!(
+        "http",
+        request_id = request_id::current().as_deref().unwrap_or_default(),
+        from = %ip,
+        method = %req.method(),
+        uri = %req.uri().path(),
+        route,
+        user_id = Empty,
+    );
+    #[cfg(feature = "otel")]
+    crate::otel::set_parent(&span, req.headers());
+    span
+}
+
+/// Attach the authenticated user to the current request span.
+pub fn record_user(user: &user::Model) {
+    Span::current().record("user_id", user.id);
+}
+
+/// Write the access log line, used as `TraceLayer::on_response`.
+///
+/// `bytes` is the body length when known up front, it is missing for
+/// streamed bodies such as event streams.
+pub fn on_response(resp: &Response, latency: Duration, _span: &Span) {
+    let bytes = resp.body().size_hint().exact().or_else(|| {
+        resp.headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok()?.parse().ok())
+    });
+    info!(
+        status = resp.status().as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        bytes,
+        "request finished"
+    );
+}
@@ -1,19 +1,45 @@
-use axum::{extract::Request, middleware::Next, response::IntoResponse, Extension};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::IntoResponse,
+    Extension,
+};
 use wr_database::user;
 
-use crate::ResponseError;
+use crate::{
+    policy::{Authorize, Permission},
+    ResponseError,
+};
 
-pub async fn admin_required(
+/// Reject the request unless the current user holds the permission given as
+/// the middleware state:
+///
+/// ```ignore
+/// .route_layer(middleware::from_fn_with_state(
+///     Permission::ManageUsers,
+///     auth::permission_required,
+/// ))
+/// ```
+///
+/// Must run after `data::prepare_user_info`.
+pub async fn permission_required(
+    State(permission): State<Permission>,
     Extension(user): Extension<user::Model>,
     req: Request,
     next: Next,
 ) -> Result<impl IntoResponse, ResponseError> {
-    if user.is_admin {
+    if user.can(permission) {
         Ok(next.run(req).await)
     } else {
         Err(ResponseError::Forbidden(
-            "admin required".to_string(),
-            "only admin can access this resource".to_string(),
+            "permission denied".to_string(),
+            format!(
+                "{} ({:?}) lacks {:?} for {}",
+                user.name,
+                user.role(),
+                permission,
+                req.uri().path()
+            ),
         ))
     }
 }
@@ -4,10 +4,12 @@ use axum::{
     middleware::Next,
     response::IntoResponse,
 };
+use chrono::Utc;
 use tracing::warn;
 use urlencoding::decode;
 use wr_database::{user, Database};
 
+use super::access_log;
 use crate::ResponseError;
 
 pub async fn prepare_user_info(
@@ -16,13 +18,16 @@ pub async fn prepare_user_info(
     mut req: Request,
     next: Next,
 ) -> Result<impl IntoResponse, ResponseError> {
-    if let Some(email) = header.get("x-email") {
-        let email = decode(email.to_str()?)?.to_string();
-        let user = user::get_by_email(&db.conn, &email).await?;
-        if let Some(user) = user {
-            req.extensions_mut().insert(user);
-            Ok(next.run(req).await)
-        } else {
+    let Some(email) = header.get("x-email") else {
+        warn!("no x-email header found, original req: {:?}", header);
+        return Err(ResponseError::Unauthorized(
+            "please login first".to_string(),
+        ));
+    };
+    let email = decode(email.to_str()?)?.to_string();
+    let user = match user::get_by_email(&db.conn, &email).await? {
+        Some(user) => user,
+        None => {
             let Some(nickname) = header.get("x-nickname") else {
                 warn!(
                     "x-email not found and no x-nickname header, original req: {:?}",
@@ -33,21 +38,81 @@ pub async fn prepare_user_info(
                 ));
             };
             let nickname = decode(nickname.to_str()?)?.to_string();
-            let user = user::get_by_name(&db.conn, &nickname).await?;
-            if let Some(user) = user {
-                req.extensions_mut().insert(user);
-                Ok(next.run(req).await)
-            } else {
+            let Some(user) = user::get_by_name(&db.conn, &nickname).await? else {
                 warn!("user not found: {}, original req: {:?}", email, header);
-                Err(ResponseError::Unauthorized(
+                return Err(ResponseError::Unauthorized(
                     "please login first".to_string(),
-                ))
-            }
+                ));
+            };
+            user
         }
-    } else {
-        warn!("no x-email header found, original req: {:?}", header);
-        Err(ResponseError::Unauthorized(
-            "please login first".to_string(),
-        ))
+    };
+    access_log::record_user(&user);
+    check_ban(&user, req.uri().path())?;
+    req.extensions_mut().insert(user);
+    Ok(next.run(req).await)
+}
+
+/// Reject banned users with a message that tells them why and for how long.
+pub fn check_ban(user: &user::Model, path: &str) -> Result<(), ResponseError> {
+    if !user.is_banned_at(Utc::now()) {
+        return Ok(());
+    }
+    Err(ResponseError::Banned {
+        until: user.banned_until,
+        reason: user.ban_reason.clone().filter(|r| !r.is_empty()),
+        detail: format!("banned user {} tried to access {path}", user.name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use chrono::Duration;
+    use serde_json::Value;
+
+    use super::*;
+
+    async fn body(err: ResponseError) -> (StatusCode, Value) {
+        let resp = err.into_response();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn banned_users_are_told_why_and_until_when() {
+        let permanent = user::Model {
+            name: "mallory".to_string(),
+            is_banned: true,
+            ban_reason: Some("spam".to_string()),
+            ..Default::default()
+        };
+        let (status, json) = body(check_ban(&permanent, "/api/report").unwrap_err()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json["code"], "account_banned");
+        assert_eq!(json["details"]["until"], Value::Null);
+        assert_eq!(json["details"]["reason"], "spam");
+        assert_eq!(json["message"], "your account is banned: spam");
+        // who tried what only goes to the log
+        assert!(!json.to_string().contains("/api/report"));
+
+        let until = Utc::now() + Duration::hours(1);
+        let temporary = user::Model {
+            banned_until: Some(until),
+            ban_reason: Some(String::new()),
+            ..permanent.clone()
+        };
+        let (_, json) = body(check_ban(&temporary, "/").unwrap_err()).await;
+        assert_eq!(json["details"]["until"], until.timestamp());
+        assert_eq!(json["details"]["reason"], Value::Null);
+
+        let expired = user::Model {
+            banned_until: Some(Utc::now() - Duration::hours(1)),
+            ..permanent
+        };
+        assert!(check_ban(&expired, "/").is_ok());
     }
 }
@@ -1,66 +1,152 @@
 use std::{
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
+    sync::Arc,
 };
 
 use axum::{
-    extract::{ConnectInfo, Request},
-    http::{header::FORWARDED, HeaderMap},
+    extract::{ConnectInfo, FromRef, FromRequestParts, Request, State},
+    http::{
+        header::{FORWARDED, HOST},
+        request::Parts,
+        uri::Authority,
+        Extensions, HeaderMap, Uri,
+    },
+    middleware::Next,
+    response::Response,
 };
+use ipnet::IpNet;
 use thiserror::Error;
-use tracing::error;
+
+use crate::{listener::UnixPeer, settings::Settings};
 
 const X_REAL_IP: &str = "x-real-ip";
 const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// Base URL of links when the request does not tell the host.
+const FALLBACK_URL: &str = "http://localhost";
+
+/// Networks of the reverse proxies whose forwarding headers are believed.
+/// Headers from any other peer are ignored, as the client can set them to
+/// anything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustedProxies(Arc<[IpNet]>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        TrustedProxies(networks.into())
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Loopback only, for a reverse proxy on the same host.
+impl Default for TrustedProxies {
+    fn default() -> Self {
+        TrustedProxies::new(vec![
+            IpNet::from(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            IpNet::from(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        ])
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
 
-/// Tries to parse the `x-forwarded-for` header
-fn maybe_x_forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
-    headers
-        .get(X_FORWARDED_FOR)
-        .and_then(|hv| hv.to_str().ok())
-        .and_then(|s| s.split(',').find_map(|s| s.trim().parse::<IpAddr>().ok()))
-}
-
-/// Tries to parse the `x-real-ip` header
-fn maybe_x_real_ip(headers: &HeaderMap) -> Option<IpAddr> {
-    headers
-        .get(X_REAL_IP)
-        .and_then(|hv| hv.to_str().ok())
-        .and_then(|s| s.parse::<IpAddr>().ok())
-}
-
-/// Tries to parse `forwarded` headers
-fn maybe_forwarded(headers: &HeaderMap) -> Option<IpAddr> {
-    headers.get_all(FORWARDED).iter().find_map(|hv| {
-        hv.to_str()
-            .ok()
-            .and_then(|s| ForwardedHeaderValue::from_forwarded(s).ok())
-            .and_then(|f| {
-                f.values
-                    .iter()
-                    .filter_map(|fs| fs.forwarded_for.as_ref())
-                    .find_map(|ff| match ff {
-                        Identifier::SocketAddr(a) => Some(a.ip()),
-                        Identifier::IpAddr(ip) => Some(*ip),
-                        _ => None,
-                    })
+    /// A comma separated list of networks or single addresses, e.g.
+    /// `127.0.0.1, 10.0.0.0/8`. Empty trusts no proxy.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<IpNet>()
+                    .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("`{s}` is not a network like 10.0.0.0/8"))
             })
-    })
+            .collect::<Result<_, _>>()
+            .map(TrustedProxies::new)
+    }
+}
+
+/// Addresses of the hops a request went through, as told by the last proxy,
+/// client first. `None` stands for a hop without a usable address such as
+/// `unknown` or an obfuscated identifier.
+///
+/// `x-forwarded-for` wins over `x-real-ip`, which wins over `forwarded`, so
+/// the proxies must set or overwrite the header that is looked at first.
+fn hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let list = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|hv| hv.to_str().ok())
+            .flat_map(values_from_header)
+            .map(|s| s.parse::<Identifier>().ok().and_then(|id| id.ip()))
+            .collect::<Vec<_>>()
+    };
+    let hops = list(X_FORWARDED_FOR);
+    if !hops.is_empty() {
+        return hops;
+    }
+    let hops = list(X_REAL_IP);
+    if !hops.is_empty() {
+        return hops;
+    }
+    forwarded(headers)
+        .map(|f| {
+            f.values
+                .iter()
+                .filter_map(|stanza| stanza.forwarded_for.as_ref())
+                .map(Identifier::ip)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// All `forwarded` headers, merged in order. `None` when there is none or one
+/// of them is malformed.
+fn forwarded(headers: &HeaderMap) -> Option<ForwardedHeaderValue> {
+    let mut values = Vec::new();
+    for hv in headers.get_all(FORWARDED) {
+        let value = ForwardedHeaderValue::from_forwarded(hv.to_str().ok()?).ok()?;
+        values.extend(value.values);
+    }
+    (!values.is_empty()).then_some(ForwardedHeaderValue { values })
 }
 
 /// Looks in `ConnectInfo` extension
-fn maybe_connect_info<B: Send>(req: &Request<B>) -> Option<IpAddr> {
-    req.extensions()
+fn maybe_connect_info(extensions: &Extensions) -> Option<IpAddr> {
+    extensions
         .get::<ConnectInfo<SocketAddr>>()
         .map(|ConnectInfo(addr)| addr.ip())
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+/// The request came over a Unix domain socket, see [`UnixPeer`].
+fn over_unix_socket(extensions: &Extensions) -> bool {
+    extensions.get::<ConnectInfo<UnixPeer>>().is_some()
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Protocol {
     Http,
     Https,
 }
 
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Http => "http",
+            Protocol::Https => "https",
+        }
+    }
+}
+
 impl FromStr for Protocol {
     type Err = ForwardedHeaderValueParseError;
 
@@ -73,7 +159,7 @@ impl FromStr for Protocol {
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Identifier {
     SocketAddr(SocketAddr),
     IpAddr(IpAddr),
@@ -81,6 +167,16 @@ pub enum Identifier {
     Unknown,
 }
 
+impl Identifier {
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Identifier::SocketAddr(addr) => Some(addr.ip()),
+            Identifier::IpAddr(ip) => Some(*ip),
+            Identifier::String(_) | Identifier::Unknown => None,
+        }
+    }
+}
+
 impl FromStr for Identifier {
     type Err = ForwardedHeaderValueParseError;
 
@@ -110,7 +206,7 @@ impl FromStr for Identifier {
 }
 
 #[derive(Default)]
-struct ForwardedStanza {
+pub struct ForwardedStanza {
     pub forwarded_by: Option<Identifier>,
     pub forwarded_for: Option<Identifier>,
     pub forwarded_host: Option<String>,
@@ -209,11 +305,23 @@ fn values_from_header(header_value: &str) -> impl Iterator<Item = &str> {
     })
 }
 
-struct ForwardedHeaderValue {
+pub struct ForwardedHeaderValue {
     values: Vec<ForwardedStanza>,
 }
 
 impl ForwardedHeaderValue {
+    /// Protocol of the original request, as recorded by the proxy nearest to
+    /// this server. Stanzas further left may come from the client itself.
+    pub fn proto(&self) -> Option<Protocol> {
+        self.values.last()?.forwarded_proto
+    }
+
+    /// `Host` of the original request, as recorded by the proxy nearest to
+    /// this server.
+    pub fn host(&self) -> Option<&str> {
+        self.values.last()?.forwarded_host.as_deref()
+    }
+
     pub fn from_forwarded(header_value: &str) -> Result<Self, ForwardedHeaderValueParseError> {
         values_from_header(header_value)
             .map(|stanza| stanza.parse::<ForwardedStanza>())
@@ -260,28 +368,279 @@ impl FromStr for ForwardedHeaderValue {
     }
 }
 
+/// Where a request comes from, resolved once per request by [`resolve`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClientAddr {
+    pub ip: Option<IpAddr>,
+    /// The peer is a trusted proxy, so its forwarding headers were used.
+    pub proxied: bool,
+}
+
+/// Resolve the client address of every request, see [`get_client_ip`].
+/// Should run before anything that looks at the client address.
+pub async fn resolve(
+    State(trusted): State<TrustedProxies>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let client = resolve_client_ip(req.headers(), req.extensions(), &trusted);
+    req.extensions_mut().insert(client);
+    next.run(req).await
+}
+
 /// Get the client IP address from the request.
 ///
-/// This function will try to get the client IP address from the following
-/// sources:
+/// The address of the peer, from the `ConnectInfo` extension, is the client
+/// unless it is one of the [`TrustedProxies`] or the request came over a Unix
+/// domain socket. Then the hops recorded in the `x-forwarded-for`,
+/// `x-real-ip` or `forwarded` header are walked from the right, skipping
+/// trusted proxies, and the first other address is the client. A hop without
+/// an address stops the walk at the proxy that reported it.
 ///
-/// - `x-forwarded-for` header
-/// - `x-real-ip` header
-/// - `forwarded` header
-/// - `ConnectInfo` extension
-///
-/// The order of precedence is as listed above. We put headers first because the
-/// physical IP address from `ConnectInfo` extension is not always correct.
-/// Nginx and other reverse proxies will shadow the real IP with `127.0.0.1`, to
-/// solve this problem, then will set the `x-forwarded-for` header to the client
-/// IP address, so we just use it.
-///
-/// In some cases, the `x-forwarded-for` header may not set, the IP record will
-/// be localhost, so please make sure the reverse proxy is configured correctly.
+/// Nothing left of the client is looked at: the client can put anything
+/// there, and each proxy only appends the address it got the request from.
 pub fn get_client_ip(request: &Request) -> Option<IpAddr> {
-    let headers = request.headers();
-    maybe_x_forwarded_for(headers)
-        .or_else(|| maybe_x_real_ip(headers))
-        .or_else(|| maybe_forwarded(headers))
-        .or_else(|| maybe_connect_info(request))
+    client_addr(request.extensions()).ip
+}
+
+/// The address resolved by [`resolve`], or the peer when it did not run.
+fn client_addr(extensions: &Extensions) -> ClientAddr {
+    extensions
+        .get::<ClientAddr>()
+        .copied()
+        .unwrap_or_else(|| ClientAddr {
+            ip: maybe_connect_info(extensions),
+            proxied: false,
+        })
+}
+
+fn resolve_client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trusted: &TrustedProxies,
+) -> ClientAddr {
+    let peer = maybe_connect_info(extensions);
+    if !(peer.is_some_and(|ip| trusted.contains(ip)) || over_unix_socket(extensions)) {
+        return ClientAddr {
+            ip: peer,
+            proxied: false,
+        };
+    }
+    let mut client = peer;
+    for hop in hops(headers).into_iter().rev() {
+        let Some(ip) = hop else { break };
+        client = Some(ip);
+        if !trusted.contains(ip) {
+            break;
+        }
+    }
+    ClientAddr {
+        ip: client,
+        proxied: true,
+    }
+}
+
+/// Extractor for the client IP address, resolved the same way as
+/// [`get_client_ip`]. Never rejects, the address is `None` when it cannot be
+/// determined.
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp(client_addr(&parts.extensions).ip))
+    }
+}
+
+/// Base URL of the site as the client sees it, without trailing slash, for
+/// absolute links.
+///
+/// The configured `server.public_url` wins. Otherwise the scheme and host
+/// come from the `forwarded` or `x-forwarded-proto` / `x-forwarded-host`
+/// headers when the peer is a trusted proxy (see [`resolve`]), and from the
+/// `Host` header of the request itself when it is not.
+pub struct PublicUrl(pub String);
+
+impl<S> FromRequestParts<S> for PublicUrl
+where
+    Arc<Settings>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let settings = Arc::<Settings>::from_ref(state);
+        if let Some(url) = &settings.server.public_url {
+            return Ok(PublicUrl(url.clone()));
+        }
+        let proxied = client_addr(&parts.extensions).proxied;
+        let scheme = match settings.server.tls {
+            Some(_) => Protocol::Https,
+            None => Protocol::Http,
+        };
+        Ok(PublicUrl(request_url(
+            &parts.headers,
+            &parts.uri,
+            proxied,
+            scheme,
+        )))
+    }
+}
+
+/// `scheme` is the one of the connection, when neither a proxy nor the URI
+/// tells.
+fn request_url(headers: &HeaderMap, uri: &Uri, proxied: bool, scheme: Protocol) -> String {
+    // Like `x-forwarded-for`, the value appended by the last proxy.
+    let last = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|hv| hv.to_str().ok())
+            .flat_map(values_from_header)
+            .last()
+    };
+    let forwarded = forwarded(headers).filter(|_| proxied);
+    let proto = forwarded
+        .as_ref()
+        .and_then(ForwardedHeaderValue::proto)
+        .or_else(|| last(X_FORWARDED_PROTO).and_then(|p| p.parse().ok()))
+        .filter(|_| proxied)
+        .or_else(|| uri.scheme_str().and_then(|s| s.parse().ok()))
+        .unwrap_or(scheme);
+    let host = forwarded
+        .as_ref()
+        .and_then(ForwardedHeaderValue::host)
+        .or_else(|| last(X_FORWARDED_HOST))
+        .filter(|h| proxied && is_host(h))
+        .or_else(|| headers.get(HOST)?.to_str().ok().filter(|h| is_host(h)))
+        .or_else(|| {
+            uri.authority()
+                .map(Authority::as_str)
+                .filter(|h| is_host(h))
+        });
+    match host {
+        Some(host) => format!("{}://{host}", proto.as_str()),
+        None => FALLBACK_URL.to_string(),
+    }
+}
+
+/// A host name or address with an optional port, nothing that would need
+/// escaping in a link.
+fn is_host(s: &str) -> bool {
+    s.parse::<Authority>().is_ok()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b".-:[]".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(peer: &str, headers: &[(&'static str, &str)]) -> ClientAddr {
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 40000)));
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, value.parse().unwrap());
+        }
+        let trusted = "127.0.0.1, 10.0.0.0/8".parse().unwrap();
+        resolve_client_ip(&map, &extensions, &trusted)
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn headers_of_untrusted_peers_are_ignored() {
+        let addr = client("203.0.113.7", &[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(addr.ip, ip("203.0.113.7"));
+        assert!(!addr.proxied);
+    }
+
+    #[test]
+    fn hops_are_walked_from_the_right() {
+        // the client made up 1.2.3.4, the proxies appended the rest
+        let headers = [
+            ("x-forwarded-for", "1.2.3.4, 198.51.100.1"),
+            ("x-forwarded-for", "10.0.0.2"),
+        ];
+        let addr = client("127.0.0.1", &headers);
+        assert_eq!(addr.ip, ip("198.51.100.1"));
+        assert!(addr.proxied);
+
+        let headers = [(
+            "forwarded",
+            r#"for="[2001:db8::1]:4711";proto=https, for=10.0.0.3"#,
+        )];
+        assert_eq!(client("10.0.0.2", &headers).ip, ip("2001:db8::1"));
+
+        let headers = [("x-forwarded-for", "unknown, 10.0.0.3")];
+        assert_eq!(client("10.0.0.2", &headers).ip, ip("10.0.0.3"));
+        assert_eq!(client("::ffff:127.0.0.1", &[]).ip, ip("::ffff:127.0.0.1"));
+    }
+
+    #[test]
+    fn unix_socket_peers_are_trusted() {
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(UnixPeer));
+        let mut headers = HeaderMap::new();
+        let trusted = TrustedProxies::default();
+        let addr = resolve_client_ip(&headers, &extensions, &trusted);
+        assert_eq!(
+            addr,
+            ClientAddr {
+                ip: None,
+                proxied: true
+            }
+        );
+
+        headers.insert(X_FORWARDED_FOR, "1.2.3.4, 198.51.100.1".parse().unwrap());
+        let addr = resolve_client_ip(&headers, &extensions, &trusted);
+        assert_eq!(addr.ip, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn forwarded_exposes_proto_and_host() {
+        let value: ForwardedHeaderValue =
+            r#"for=1.2.3.4;proto=http, for=10.0.0.1;proto=https;host="wr.example.com""#
+                .parse()
+                .unwrap();
+        assert_eq!(value.proto(), Some(Protocol::Https));
+        assert_eq!(value.host(), Some("wr.example.com"));
+    }
+
+    #[test]
+    fn public_url_follows_trusted_proxies_only() {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, "10.0.0.5:8080".parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "https".parse().unwrap());
+        headers.insert(X_FORWARDED_HOST, "wr.example.com".parse().unwrap());
+        let uri = Uri::from_static("/api/user/1/feed/public");
+        assert_eq!(
+            request_url(&headers, &uri, true, Protocol::Http),
+            "https://wr.example.com"
+        );
+        assert_eq!(
+            request_url(&headers, &uri, false, Protocol::Http),
+            "http://10.0.0.5:8080"
+        );
+
+        headers.insert(
+            FORWARDED,
+            "for=1.2.3.4;proto=http;host=a.example.com".parse().unwrap(),
+        );
+        assert_eq!(
+            request_url(&headers, &uri, true, Protocol::Http),
+            "http://a.example.com"
+        );
+
+        headers.clear();
+        headers.insert(HOST, "evil.com/<script>".parse().unwrap());
+        assert_eq!(
+            request_url(&headers, &uri, false, Protocol::Http),
+            FALLBACK_URL
+        );
+    }
 }
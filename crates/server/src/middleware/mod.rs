@@ -1,3 +1,6 @@
+pub mod access_log;
 pub mod auth;
 pub mod data;
 pub mod forwarded;
+pub mod rate_limit;
+pub mod request_id;
@@ -0,0 +1,307 @@
+//! Token bucket rate limiting.
+//!
+//! Every route group gets its own [`RateLimiter`] with a [`Quota`]. Requests
+//! are keyed by the logged-in user when there is one, by client IP otherwise.
+//! Requests without either are not limited, one shared bucket would let a
+//! single client use up the quota of all of them.
+//! A bucket holds up to `burst` tokens and refills at `burst / period`, so a
+//! client can spend its whole quota at once and then continues at the average
+//! rate.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, Mutex, Once},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::warn;
+use wr_database::user;
+
+use super::forwarded;
+use crate::ResponseError;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RETRY_AFTER: HeaderName = HeaderName::from_static("retry-after");
+
+/// Drop idle buckets once the map grows past this many entries.
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// `burst` requests per `period`, written as `30/60` or `30/60s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl Quota {
+    pub const fn per_minute(burst: u32) -> Self {
+        Quota {
+            burst,
+            period: Duration::from_secs(60),
+        }
+    }
+
+    /// Tokens refilled per second.
+    fn rate(&self) -> f64 {
+        self.burst as f64 / self.period.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}s", self.burst, self.period.as_secs())
+    }
+}
+
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rate limit `{s}`, expected e.g. `30/60s`");
+        let (burst, period) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let burst: u32 = burst.trim().parse().map_err(|_| invalid())?;
+        let period: u64 = period
+            .trim()
+            .trim_end_matches('s')
+            .parse()
+            .map_err(|_| invalid())?;
+        if burst == 0 || period == 0 {
+            return Err(invalid());
+        }
+        Ok(Quota {
+            burst,
+            period: Duration::from_secs(period),
+        })
+    }
+}
+
+/// Quotas of the route groups, `None` disables limiting for the group.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    /// Feed and other unauthenticated routes.
+    pub feed: Option<Quota>,
+    /// Liking and unliking reports.
+    pub react: Option<Quota>,
+    /// Everything else behind login.
+    pub read: Option<Quota>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            feed: Some(Quota::per_minute(30)),
+            react: Some(Quota::per_minute(20)),
+            read: Some(Quota::per_minute(300)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    User(i32),
+    Ip(IpAddr),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::User(id) => write!(f, "user:{id}"),
+            Key::Ip(ip) => write!(f, "ip:{ip}"),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Outcome of taking a token from a bucket.
+#[derive(Debug, PartialEq)]
+struct Decision {
+    allowed: bool,
+    remaining: u32,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+    /// Seconds until the next token, only meaningful when not allowed.
+    retry_after: u64,
+}
+
+pub struct RateLimiter {
+    group: &'static str,
+    quota: Quota,
+    buckets: Mutex<HashMap<Key, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(group: &'static str, quota: Quota) -> Arc<Self> {
+        Arc::new(RateLimiter {
+            group,
+            quota,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn check(&self, key: Key, now: Instant) -> Decision {
+        let burst = self.quota.burst as f64;
+        let rate = self.quota.rate();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            // a bucket that would be full by now carries no state
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        Decision {
+            allowed,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((burst - bucket.tokens) / rate).ceil() as u64,
+            retry_after: ((1.0 - bucket.tokens).max(0.0) / rate).ceil() as u64,
+        }
+    }
+
+    fn insert_headers(&self, headers: &mut HeaderMap, decision: &Decision) {
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.quota.burst));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(decision.reset));
+    }
+}
+
+/// Limit the requests of a route group:
+///
+/// ```ignore
+/// .route_layer(middleware::from_fn_with_state(
+///     RateLimiter::new("react", quota),
+///     rate_limit::rate_limit,
+/// ))
+/// ```
+///
+/// Requests are keyed by user when it runs after `data::prepare_user_info`,
+/// by client IP otherwise.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    static UNKNOWN_CLIENT: Once = Once::new();
+    let key = match req.extensions().get::<user::Model>() {
+        Some(user) => Key::User(user.id),
+        None => match forwarded::get_client_ip(&req) {
+            Some(ip) => Key::Ip(ip),
+            None => {
+                UNKNOWN_CLIENT.call_once(|| {
+                    warn!(
+                        "requests without a client address are not rate limited, \
+                         make the proxy send X-Forwarded-For"
+                    )
+                });
+                return next.run(req).await;
+            }
+        },
+    };
+    let decision = limiter.check(key, Instant::now());
+    if !decision.allowed {
+        let mut resp = ResponseError::TooManyRequests(
+            "too many requests, please slow down".to_string(),
+            format!(
+                "{key} exceeded {} on {} ({})",
+                limiter.quota,
+                limiter.group,
+                req.uri().path()
+            ),
+        )
+        .into_response();
+        limiter.insert_headers(resp.headers_mut(), &decision);
+        resp.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(decision.retry_after.max(1)));
+        return resp;
+    }
+    let mut resp = next.run(req).await;
+    limiter.insert_headers(resp.headers_mut(), &decision);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_quota() {
+        assert_eq!("30/60s".parse(), Ok(Quota::per_minute(30)));
+        assert_eq!(" 30 / 60 ".parse(), Ok(Quota::per_minute(30)));
+        assert!("30".parse::<Quota>().is_err());
+        assert!("0/60".parse::<Quota>().is_err());
+        assert!("30/0s".parse::<Quota>().is_err());
+    }
+
+    #[test]
+    fn bucket_spends_burst_then_refills() {
+        let limiter = RateLimiter::new("test", Quota::per_minute(2));
+        let key = Key::User(1);
+        let start = Instant::now();
+        assert!(limiter.check(key, start).allowed);
+        assert!(limiter.check(key, start).allowed);
+        let denied = limiter.check(key, start);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after, 30);
+        // other clients are unaffected
+        assert!(limiter.check(Key::User(2), start).allowed);
+        // one token comes back every 30 seconds
+        let later = limiter.check(key, start + Duration::from_secs(30));
+        assert!(later.allowed);
+        assert_eq!(later.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn clients_without_an_address_are_not_limited() {
+        use axum::{body::Body, extract::ConnectInfo, http::StatusCode, routing::get, Router};
+        use std::net::SocketAddr;
+        use tower::ServiceExt;
+
+        let router = Router::new().route("/", get(|| async { "ok" })).layer(
+            axum::middleware::from_fn_with_state(
+                RateLimiter::new("test", Quota::per_minute(1)),
+                rate_limit,
+            ),
+        );
+        let send = |peer: Option<SocketAddr>| {
+            let mut req = Request::new(Body::empty());
+            if let Some(peer) = peer {
+                req.extensions_mut().insert(ConnectInfo(peer));
+            }
+            router.clone().oneshot(req)
+        };
+        let peer = Some(SocketAddr::from(([192, 0, 2, 1], 4000)));
+        assert_eq!(send(peer).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(
+            send(peer).await.unwrap().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        for _ in 0..3 {
+            assert_eq!(send(None).await.unwrap().status(), StatusCode::OK);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest request id accepted from a client or proxy.
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: Arc<str>;
+}
+
+/// Id of the request being handled by the current task, if any.
+pub fn current() -> Option<Arc<str>> {
+    REQUEST_ID.try_with(Arc::clone).ok()
+}
+
+/// Reuse a sane `x-request-id` from upstream, otherwise make up a new one.
+fn request_id(req: &Request) -> Arc<str> {
+    req.headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= MAX_LEN)
+        .filter(|v| v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(Arc::from)
+        .unwrap_or_else(|| Arc::from(Uuid::new_v4().to_string()))
+}
+
+/// Give every request an id, echoed in the `x-request-id` response header and
+/// in error bodies. Should be the outermost layer.
+pub async fn propagate(req: Request, next: Next) -> Response {
+    let id = request_id(&req);
+    let mut resp = REQUEST_ID.scope(id.clone(), next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        resp.headers_mut().insert(X_REQUEST_ID, value);
+    }
+    resp
+}
@@ -0,0 +1,132 @@
+//! Export of spans to an OpenTelemetry collector over OTLP, compiled with the
+//! `otel` feature.
+//!
+//! Incoming W3C `traceparent` headers make the request span a child of the
+//! caller's span, so the server shows up inside distributed traces.
+
+use axum::http::HeaderMap;
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+use crate::logging::{BoxedLayer, OtelSettings, OtlpProtocol};
+
+/// Path of the trace endpoint below the base URL of an OTLP/HTTP collector.
+const HTTP_TRACES_PATH: &str = "/v1/traces";
+
+/// Build the provider exporting spans in batches, and accept W3C trace
+/// context from now on.
+pub fn provider(settings: &OtelSettings) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = match settings.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&settings.endpoint)
+            .build()?,
+        OtlpProtocol::Http => {
+            let base = settings.endpoint.trim_end_matches('/');
+            let endpoint = if base.ends_with(HTTP_TRACES_PATH) {
+                base.to_string()
+            } else {
+                format!("{base}{HTTP_TRACES_PATH}")
+            };
+            SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()?
+        }
+    };
+    let resource = Resource::builder()
+        .with_service_name(settings.service_name.clone())
+        .build();
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(SdkTracerProvider::builder()
+        .with_resource(resource)
+        .with_batch_exporter(exporter)
+        .build())
+}
+
+pub fn layer<S>(provider: &SdkTracerProvider) -> BoxedLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+        .boxed()
+}
+
+/// Continue the trace of the caller, if it sent one.
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    let parent = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+    // Fails only when spans are not exported.
+    let _ = span.set_parent(parent);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{body::Bytes, extract::State, routing::post, Router};
+    use tokio::net::TcpListener;
+    use tracing::info_span;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    /// Stands in for a collector, keeps the bodies posted to it.
+    async fn collector() -> (String, Arc<Mutex<Vec<Bytes>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                HTTP_TRACES_PATH,
+                post(
+                    |State(received): State<Arc<Mutex<Vec<Bytes>>>>, body: Bytes| async move {
+                        received.lock().unwrap().push(body);
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (format!("http://{addr}"), received)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_spans_under_the_callers_trace() {
+        let (endpoint, received) = collector().await;
+        let provider = provider(&OtelSettings {
+            endpoint,
+            protocol: OtlpProtocol::Http,
+            service_name: "wr-server-test".to_string(),
+        })
+        .unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            let mut headers = HeaderMap::new();
+            let traceparent = format!("00-{TRACE_ID}-00f067aa0ba902b7-01");
+            headers.insert("traceparent", traceparent.parse().unwrap());
+            let span = info_span!("http");
+            set_parent(&span, &headers);
+            span.in_scope(|| info_span!("report::get").in_scope(|| {}));
+        });
+        tokio::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let trace_id = hex::decode(TRACE_ID).unwrap();
+        let body = &received[0];
+        assert!(body.windows(trace_id.len()).any(|w| w == trace_id));
+        for name in [&b"report::get"[..], b"wr-server-test"] {
+            assert!(body.windows(name.len()).any(|w| w == name));
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Authorization policy: which role is allowed to do what, and what they get
+//! to see.
+//!
+//! Routes declare the [`Permission`] they need with
+//! [`crate::middleware::auth::permission_required`], handlers that need a
+//! finer decision ask the current user directly through [`Authorize::can`].
+//! Reports are filtered through the reader's [`audience`], users are only
+//! ever returned as a [`UserDto`] built by [`project_user`].
+
+use chrono::{serde::ts_seconds_option, DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use wr_database::{
+    report::{Audience, PrivateAccess, Visibility},
+    user::{self, Role},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Submit and edit one's own weekly report.
+    SubmitReport,
+    /// Like and unlike other members' reports.
+    React,
+    /// Comment on other members' reports. Nothing offers comments yet, the
+    /// permission is granted ahead of them.
+    Comment,
+    /// List hidden users.
+    ReadHiddenUsers,
+    /// Read private reports of members in one's own direction.
+    ReadPrivateReports,
+    /// Extend `ReadPrivateReports` to members of every direction.
+    ReadOtherDirections,
+    /// Import, modify, ban and unban users.
+    ManageUsers,
+    /// Change the term configuration.
+    ManageConfig,
+    /// Read the audit log.
+    ViewAuditLog,
+    /// Read the term statistics.
+    ViewStats,
+}
+
+const MEMBER: &[Permission] = &[Permission::SubmitReport, Permission::React];
+
+const MENTOR: &[Permission] = &[
+    Permission::SubmitReport,
+    Permission::React,
+    Permission::Comment,
+    Permission::ReadHiddenUsers,
+    Permission::ReadPrivateReports,
+];
+
+/// A mentor who also oversees the other directions.
+const DIRECTION_LEAD: &[Permission] = &[
+    Permission::SubmitReport,
+    Permission::React,
+    Permission::Comment,
+    Permission::ReadHiddenUsers,
+    Permission::ReadPrivateReports,
+    Permission::ReadOtherDirections,
+    Permission::ViewStats,
+];
+
+const ADMIN: &[Permission] = &[
+    Permission::SubmitReport,
+    Permission::React,
+    Permission::Comment,
+    Permission::ReadHiddenUsers,
+    Permission::ReadPrivateReports,
+    Permission::ReadOtherDirections,
+    Permission::ManageUsers,
+    Permission::ManageConfig,
+    Permission::ViewAuditLog,
+    Permission::ViewStats,
+];
+
+pub fn permissions(role: Role) -> &'static [Permission] {
+    match role {
+        Role::Member => MEMBER,
+        Role::Mentor => MENTOR,
+        Role::DirectionLead => DIRECTION_LEAD,
+        Role::Admin => ADMIN,
+    }
+}
+
+pub trait Authorize {
+    fn can(&self, permission: Permission) -> bool;
+}
+
+impl Authorize for user::Model {
+    fn can(&self, permission: Permission) -> bool {
+        permissions(self.role()).contains(&permission)
+    }
+}
+
+/// The audience a user reads reports as, see [`Audience`] for what each one
+/// may see.
+pub fn audience(user: &user::Model) -> Audience {
+    let private = if !user.can(Permission::ReadPrivateReports) {
+        PrivateAccess::None
+    } else if user.can(Permission::ReadOtherDirections) {
+        PrivateAccess::All
+    } else {
+        match user.direction.clone() {
+            Some(direction) => PrivateAccess::Direction(direction),
+            None => PrivateAccess::None,
+        }
+    };
+    Audience::Member {
+        user_id: user.id,
+        private,
+        include_hidden: user.can(Permission::ReadHiddenUsers),
+    }
+}
+
+/// Whether `viewer` may see `user` at all.
+pub fn can_see_user(viewer: &user::Model, user: &user::Model) -> bool {
+    !user.is_hidden || viewer.id == user.id || viewer.can(Permission::ReadHiddenUsers)
+}
+
+/// A user as returned by the API.
+#[derive(Serialize, ToSchema)]
+pub struct UserDto {
+    pub id: i32,
+    pub name: String,
+    pub direction: Option<String>,
+    pub level: i32,
+    pub role: Role,
+    pub is_hidden: bool,
+    pub is_admin: bool,
+    /// Only for the user themself and user managers.
+    #[serde(flatten)]
+    #[schema(value_type = AccountDto)]
+    pub account: Option<AccountDto>,
+}
+
+/// Account details of a [`UserDto`]. They are either all present or all
+/// missing, hence none of them is required in the schema.
+#[derive(Serialize, ToSchema)]
+pub struct AccountDto {
+    #[schema(required = false)]
+    pub email: Option<String>,
+    #[schema(required = false)]
+    pub is_banned: bool,
+    #[serde(with = "ts_seconds_option")]
+    #[schema(value_type = Option<i64>, required = false)]
+    pub banned_until: Option<DateTime<Utc>>,
+    #[schema(required = false)]
+    pub ban_reason: Option<String>,
+    #[schema(required = false)]
+    pub default_visibility: Visibility,
+}
+
+/// Project `user` for `viewer`. Callers must check [`can_see_user`] first.
+pub fn project_user(viewer: &user::Model, user: user::Model) -> UserDto {
+    let role = user.role();
+    let account =
+        (viewer.id == user.id || viewer.can(Permission::ManageUsers)).then_some(AccountDto {
+            email: user.email,
+            is_banned: user.is_banned,
+            banned_until: user.banned_until,
+            ban_reason: user.ban_reason,
+            default_visibility: user.default_visibility,
+        });
+    UserDto {
+        id: user.id,
+        role,
+        name: user.name,
+        direction: user.direction,
+        level: user.level,
+        is_hidden: user.is_hidden,
+        is_admin: user.is_admin,
+        account,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn user(id: i32, role: Role, is_admin: bool) -> user::Model {
+        user::Model {
+            id,
+            name: format!("user{id}"),
+            email: Some(format!("user{id}@example.com")),
+            assigned_role: role,
+            is_admin,
+            ..Default::default()
+        }
+    }
+
+    fn keys(viewer: &user::Model, target: user::Model) -> Vec<String> {
+        let Value::Object(map) = serde_json::to_value(project_user(viewer, target)).unwrap() else {
+            panic!("user dto must serialize to an object");
+        };
+        let mut keys: Vec<_> = map.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    const PUBLIC_FIELDS: &[&str] = &[
+        "direction",
+        "id",
+        "is_admin",
+        "is_hidden",
+        "level",
+        "name",
+        "role",
+    ];
+
+    const ACCOUNT_FIELDS: &[&str] = &[
+        "ban_reason",
+        "banned_until",
+        "default_visibility",
+        "email",
+        "is_banned",
+    ];
+
+    fn with_account() -> Vec<String> {
+        let mut all: Vec<_> = PUBLIC_FIELDS
+            .iter()
+            .chain(ACCOUNT_FIELDS)
+            .map(|s| s.to_string())
+            .collect();
+        all.sort();
+        all
+    }
+
+    #[test]
+    fn roles_grant_more_permissions_as_they_rise() {
+        use Permission::*;
+
+        let member = user(1, Role::Member, false);
+        assert!(member.can(SubmitReport) && member.can(React));
+        assert!(!member.can(Comment) && !member.can(ReadPrivateReports));
+
+        let mentor = user(1, Role::Mentor, false);
+        assert!(mentor.can(Comment) && mentor.can(ReadHiddenUsers));
+        assert!(mentor.can(ReadPrivateReports) && !mentor.can(ReadOtherDirections));
+        assert!(!mentor.can(ViewStats));
+
+        let lead = user(1, Role::DirectionLead, false);
+        assert!(lead.can(ReadOtherDirections) && lead.can(ViewStats));
+        assert!(!lead.can(ManageUsers) && !lead.can(ManageConfig) && !lead.can(ViewAuditLog));
+
+        // every role has what the one below it has
+        let roles = [Role::Member, Role::Mentor, Role::DirectionLead, Role::Admin];
+        for pair in roles.windows(2) {
+            for permission in permissions(pair[0]) {
+                assert!(permissions(pair[1]).contains(permission), "{pair:?}");
+            }
+        }
+        assert!(permissions(Role::Admin).contains(&ManageConfig));
+    }
+
+    #[test]
+    fn roles_are_assigned_not_derived_from_the_grade() {
+        let senior = user::Model {
+            level: 4,
+            ..user(1, Role::Member, false)
+        };
+        assert_eq!(senior.role(), Role::Member);
+        assert!(!senior.can(Permission::ReadOtherDirections));
+
+        // only `is_admin` makes an admin
+        assert_eq!(user(1, Role::Admin, false).role(), Role::Member);
+        assert_eq!(user(1, Role::Mentor, true).role(), Role::Admin);
+    }
+
+    #[test]
+    fn direction_leads_read_private_reports_of_every_direction() {
+        let lead = user::Model {
+            direction: Some("web".into()),
+            ..user(1, Role::DirectionLead, false)
+        };
+        assert!(matches!(
+            audience(&lead),
+            Audience::Member {
+                private: PrivateAccess::All,
+                ..
+            }
+        ));
+
+        let mentor = user::Model {
+            direction: Some("web".into()),
+            ..user(1, Role::Mentor, false)
+        };
+        assert!(matches!(
+            audience(&mentor),
+            Audience::Member {
+                private: PrivateAccess::Direction(direction),
+                ..
+            } if direction == "web"
+        ));
+    }
+
+    #[test]
+    fn members_and_mentors_see_public_fields_of_others() {
+        for viewer in [
+            user(1, Role::Member, false),
+            user(1, Role::Mentor, false),
+            user(1, Role::DirectionLead, false),
+        ] {
+            assert_eq!(keys(&viewer, user(2, Role::Member, false)), PUBLIC_FIELDS);
+        }
+    }
+
+    #[test]
+    fn users_see_their_own_account() {
+        let me = user(1, Role::Member, false);
+        assert_eq!(keys(&me, me.clone()), with_account());
+    }
+
+    #[test]
+    fn admins_see_every_account() {
+        assert_eq!(
+            keys(&user(1, Role::Member, true), user(2, Role::Member, false)),
+            with_account()
+        );
+    }
+
+    #[test]
+    fn hidden_users_are_visible_to_mentors_and_themselves_only() {
+        let hidden = user::Model {
+            is_hidden: true,
+            ..user(2, Role::Member, false)
+        };
+        assert!(!can_see_user(&user(1, Role::Member, false), &hidden));
+        assert!(can_see_user(&user(1, Role::Mentor, false), &hidden));
+        assert!(can_see_user(&user(1, Role::Member, true), &hidden));
+        assert!(can_see_user(&hidden, &hidden));
+    }
+}
@@ -0,0 +1,79 @@
+use axum::{extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use wr_database::{audit_log, Database};
+
+use super::extract::{Json, Query};
+use crate::{traits::ErrorBody, ResponseError};
+
+const DEFAULT_PER_PAGE: u64 = 50;
+const MAX_PER_PAGE: u64 = 200;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub actor: Option<i32>,
+    /// Exact action or action prefix, `user` matches `user.modify`.
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// Unix timestamp (seconds), inclusive.
+    pub from: Option<i64>,
+    /// Unix timestamp (seconds), exclusive.
+    pub to: Option<i64>,
+    /// 1-based page number.
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditPage {
+    items: Vec<audit_log::Model>,
+    page: u64,
+    per_page: u64,
+    total: u64,
+}
+
+fn timestamp(ts: Option<i64>, name: &str) -> Result<Option<DateTime<Utc>>, ResponseError> {
+    ts.map(|ts| {
+        DateTime::from_timestamp(ts, 0)
+            .ok_or_else(|| ResponseError::BadRequest(format!("invalid `{name}` timestamp")))
+    })
+    .transpose()
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, body = AuditPage),
+        (status = 400, body = ErrorBody),
+        (status = 403, description = "Missing `view_audit_log`", body = ErrorBody),
+    ),
+)]
+pub async fn get_audit_logs(
+    State(ref db): State<Database>,
+    Query(query): Query<AuditQuery>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let filter = audit_log::Filter {
+        actor_id: query.actor,
+        action: query.action,
+        target: query.target,
+        from: timestamp(query.from, "from")?,
+        to: timestamp(query.to, "to")?,
+    };
+    let (items, total) = audit_log::get_page(&db.conn, filter, page - 1, per_page).await?;
+    Ok(Json(AuditPage {
+        items,
+        page,
+        per_page,
+        total,
+    }))
+}
@@ -0,0 +1,179 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    http::HeaderName,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use futures::stream;
+use tokio::{
+    sync::broadcast::{error::RecvError, Receiver},
+    time::{self, Instant, Interval},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
+use wr_database::{report::Audience, user, Database};
+
+use crate::{
+    events::{Event, EventBus},
+    policy,
+};
+
+/// How often an open stream checks that its user may still listen.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Server-sent events about reports the user may read: `report.submitted`,
+/// `report.updated`, `report.liked` and `status.changed`, each with a JSON
+/// payload. A `resync` event without payload means that events were dropped
+/// because the client fell behind, it should reload what it shows.
+///
+/// The stream ends when the user is banned or their role, direction or
+/// visibility changes, a reconnecting client gets events for the new access.
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "report",
+    responses((status = 200, body = String, content_type = "text/event-stream")),
+)]
+pub async fn subscribe(
+    State(events): State<EventBus>,
+    State(shutdown): State<CancellationToken>,
+    State(db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+) -> impl IntoResponse {
+    debug!(user = %current_user.name, "event stream opened");
+    let subscription = Subscription {
+        receiver: events.subscribe(),
+        audience: policy::audience(&current_user),
+        shutdown,
+        db,
+        user: current_user,
+        recheck: time::interval_at(Instant::now() + RECHECK_INTERVAL, RECHECK_INTERVAL),
+    };
+    let stream = stream::unfold(subscription, next_event);
+    // keep nginx from buffering the stream
+    (
+        [(HeaderName::from_static("x-accel-buffering"), "no")],
+        Sse::new(stream).keep_alive(KeepAlive::default()),
+    )
+}
+
+struct Subscription {
+    receiver: Receiver<Arc<Event>>,
+    audience: Audience,
+    shutdown: CancellationToken,
+    db: Database,
+    /// The user as of when the stream was opened, `audience` is theirs.
+    user: user::Model,
+    recheck: Interval,
+}
+
+/// The next event for the subscriber, the stream ends with the server or when
+/// the user may no longer listen.
+async fn next_event(mut sub: Subscription) -> Option<(Result<SseEvent, Infallible>, Subscription)> {
+    loop {
+        let received = tokio::select! {
+            _ = sub.shutdown.cancelled() => return None,
+            _ = sub.recheck.tick() => {
+                match user::get(&sub.db.conn, sub.user.id).await {
+                    Ok(current) if still_allowed(&sub.user, current.as_ref(), Utc::now()) => {}
+                    Ok(_) => {
+                        debug!(user = %sub.user.name, "access changed, closing event stream");
+                        return None;
+                    }
+                    // keep the stream, the next check may succeed
+                    Err(e) => warn!(user = %sub.user.name, "failed to recheck event subscriber: {e}"),
+                }
+                continue;
+            }
+            received = sub.receiver.recv() => received,
+        };
+        let event = match received {
+            Ok(event) if event.visible_to(&sub.audience) => event,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(missed)) => {
+                debug!(missed, "event subscriber lagged behind");
+                let resync = SseEvent::default().event("resync").data("");
+                return Some((Ok(resync), sub));
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        match SseEvent::default().event(event.name()).json_data(&*event) {
+            Ok(sse) => return Some((Ok(sse), sub)),
+            Err(e) => error!(event = event.name(), "failed to serialize event: {e}"),
+        }
+    }
+}
+
+/// Whether a stream opened by `opened` may go on now that the user reads as
+/// `current`. Anything that changes what they may read ends it.
+fn still_allowed(opened: &user::Model, current: Option<&user::Model>, now: DateTime<Utc>) -> bool {
+    let Some(current) = current else {
+        return false;
+    };
+    !current.is_banned_at(now)
+        && current.role() == opened.role()
+        && current.direction == opened.direction
+        && current.is_hidden == opened.is_hidden
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration as ChronoDuration;
+
+    use super::*;
+
+    #[test]
+    fn streams_end_when_access_changes() {
+        let now = Utc::now();
+        let opened = user::Model {
+            id: 1,
+            assigned_role: user::Role::Mentor,
+            direction: Some("web".to_string()),
+            ..Default::default()
+        };
+        assert!(still_allowed(&opened, Some(&opened), now));
+        // unrelated edits keep the stream
+        let renamed = user::Model {
+            name: "renamed".to_string(),
+            ..opened.clone()
+        };
+        assert!(still_allowed(&opened, Some(&renamed), now));
+
+        let changed = [
+            user::Model {
+                is_banned: true,
+                ..opened.clone()
+            },
+            user::Model {
+                assigned_role: user::Role::Member,
+                ..opened.clone()
+            },
+            user::Model {
+                direction: Some("pwn".to_string()),
+                ..opened.clone()
+            },
+            user::Model {
+                is_hidden: true,
+                ..opened.clone()
+            },
+        ];
+        for current in &changed {
+            assert!(!still_allowed(&opened, Some(current), now));
+        }
+        assert!(!still_allowed(&opened, None, now));
+
+        // a ban that has run out no longer counts
+        let expired = user::Model {
+            is_banned: true,
+            banned_until: Some(now - ChronoDuration::hours(1)),
+            ..opened.clone()
+        };
+        assert!(still_allowed(&opened, Some(&expired), now));
+    }
+}
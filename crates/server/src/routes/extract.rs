@@ -0,0 +1,105 @@
+//! Drop-in replacements for the axum `Json`, `Query` and `Path` extractors
+//! whose rejections are [`ResponseError`]s, so a malformed body, query string
+//! or path parameter gets the same error body as every other failure instead
+//! of axum's plain text.
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::ResponseError;
+
+#[derive(Debug, Clone, Copy, Default, FromRequest)]
+#[from_request(via(axum::Json), rejection(ResponseError))]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ResponseError))]
+pub struct Query<T>(pub T);
+
+#[derive(Debug, Clone, Copy, FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ResponseError))]
+pub struct Path<T>(pub T);
+
+/// Keep the few rejections that are not the client's fault, such as a route
+/// without the path parameters its handler asks for, out of `bad_request`.
+fn rejected(status: StatusCode, text: String) -> ResponseError {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => ResponseError::PayloadTooLarge(text),
+        status if status.is_server_error() => {
+            ResponseError::InternalServerError("Failed to extract request".to_string(), text)
+        }
+        _ => ResponseError::BadRequest(text),
+    }
+}
+
+impl From<JsonRejection> for ResponseError {
+    fn from(rejection: JsonRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ResponseError {
+    fn from(rejection: QueryRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ResponseError {
+    fn from(rejection: PathRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use http_body_util::BodyExt;
+    use serde::Deserialize;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Form {
+        #[allow(dead_code)]
+        name: String,
+    }
+
+    async fn send(body: &'static str) -> (StatusCode, Value) {
+        let router = Router::new().route("/", post(|Json(_): Json<Form>| async { "ok" }));
+        let req = Request::post("/")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_bad_request() {
+        let (status, body) = send(r#"{"name": 1}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "bad_request");
+        assert!(body["message"].as_str().unwrap().contains("name"));
+
+        let (status, body) = send("{").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "bad_request");
+    }
+}
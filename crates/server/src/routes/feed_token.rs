@@ -0,0 +1,199 @@
+//! Feed tokens.
+//!
+//! A user can hold several named tokens, e.g. one per feed reader. Only the
+//! SHA-256 hash of a token is stored, so the plaintext is shown exactly once,
+//! when the token is created.
+
+use axum::{extract::State, response::IntoResponse, Extension};
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use utoipa::ToSchema;
+use wr_database::{feed_token, user, Database};
+
+use super::extract::{Json, Path};
+use crate::{audit::Auditor, traits::ErrorBody, ResponseError};
+
+/// Upper bound of active tokens per user.
+const MAX_ACTIVE_TOKENS: u64 = 20;
+const MAX_LABEL_LEN: usize = 64;
+
+fn hash(token: &str) -> Vec<u8> {
+    digest(&SHA256, token.as_bytes()).as_ref().to_vec()
+}
+
+/// Create a token for the user, returning the stored row and the plaintext.
+async fn issue(
+    db: &Database,
+    user_id: i32,
+    label: String,
+) -> Result<(feed_token::Model, String), ResponseError> {
+    let mut secret = [0u8; 32];
+    SystemRandom::new().fill(&mut secret).map_err(|_| {
+        ResponseError::InternalServerError(
+            "failed to create token".to_string(),
+            "system random generator is unavailable".to_string(),
+        )
+    })?;
+    let token = hex::encode(secret);
+    let row = feed_token::create(&db.conn, user_id, label, hex::encode(hash(&token))).await?;
+    Ok((row, token))
+}
+
+/// Resolve a presented feed token to its owner, recording the use. Revoked
+/// and unknown tokens are rejected alike.
+pub async fn authenticate(db: &Database, token: &str) -> Result<user::Model, ResponseError> {
+    let invalid = || ResponseError::Unauthorized("invalid token".to_string());
+    let presented = hash(token);
+    let row = feed_token::get_by_hash(&db.conn, &hex::encode(&presented))
+        .await?
+        .filter(|t| t.revoked_at.is_none())
+        .ok_or_else(invalid)?;
+    // the lookup already matched, but do not let the final check depend on
+    // how the database compares strings. `ring::constant_time` is deprecated
+    // and makes no promises about side channels, `subtle` does.
+    let stored = hex::decode(&row.token_hash).unwrap_or_default();
+    if !bool::from(stored.ct_eq(&presented)) {
+        return Err(invalid());
+    }
+    feed_token::touch(&db.conn, row.id).await?;
+    user::get(&db.conn, row.user_id).await?.ok_or_else(invalid)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    info: feed_token::Model,
+    /// The plaintext token, only ever returned here.
+    token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTokenForm {
+    pub label: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevokedToken {
+    revoked: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenDto {
+    token: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/self/feed_tokens",
+    tag = "feed",
+    responses((status = 200, body = Vec<feed_token::Model>)),
+)]
+pub async fn list_tokens(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+) -> Result<impl IntoResponse, ResponseError> {
+    Ok(Json(
+        feed_token::get_user_list(&db.conn, current_user.id).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/self/feed_tokens",
+    tag = "feed",
+    request_body = CreateTokenForm,
+    responses(
+        (status = 200, description = "The plaintext token is only returned here", body = CreatedToken),
+        (status = 400, body = ErrorBody),
+        (status = 409, description = "Too many active tokens", body = ErrorBody),
+    ),
+)]
+pub async fn create_token(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    auditor: Auditor,
+    Json(form): Json<CreateTokenForm>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let label = form.label.trim().to_string();
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LEN {
+        return Err(ResponseError::BadRequest(format!(
+            "label must be 1 to {MAX_LABEL_LEN} characters"
+        )));
+    }
+    if feed_token::count_active(&db.conn, current_user.id).await? >= MAX_ACTIVE_TOKENS {
+        return Err(ResponseError::Conflict(format!(
+            "at most {MAX_ACTIVE_TOKENS} active feed tokens are allowed, revoke one first"
+        )));
+    }
+    let (info, token) = issue(db, current_user.id, label).await?;
+    // never store the token itself in the audit log
+    auditor
+        .record(
+            "feed_token.create",
+            Some(format!("feed_token:{}", info.id)),
+            None,
+            Some(serde_json::json!({ "label": info.label })),
+        )
+        .await;
+    Ok(Json(CreatedToken { info, token }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/self/feed_tokens/{id}",
+    tag = "feed",
+    params(("id" = i32, Path, description = "Feed token id")),
+    responses(
+        (status = 200, body = RevokedToken),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn revoke_token(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    auditor: Auditor,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ResponseError> {
+    if !feed_token::revoke(&db.conn, current_user.id, id).await? {
+        return Err(ResponseError::NotFound("feed token not found".to_string()));
+    }
+    auditor
+        .record(
+            "feed_token.revoke",
+            Some(format!("feed_token:{id}")),
+            None,
+            None,
+        )
+        .await;
+    Ok(Json(RevokedToken { revoked: id }))
+}
+
+// Reset the current user's feed access: revoke every token and hand out a
+// fresh one labelled `default`.
+#[utoipa::path(
+    post,
+    path = "/self/feed_token",
+    tag = "feed",
+    responses((status = 200, body = TokenDto)),
+)]
+pub async fn regenerate_token(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    auditor: Auditor,
+) -> Result<impl IntoResponse, ResponseError> {
+    let revoked = feed_token::revoke_all(&db.conn, current_user.id).await?;
+    let (info, token) = issue(db, current_user.id, "default".to_string()).await?;
+    auditor
+        .record(
+            "feed_token.regenerate",
+            Some(format!("user:{}", current_user.id)),
+            Some(serde_json::json!({ "revoked": revoked })),
+            Some(serde_json::json!({ "id": info.id })),
+        )
+        .await;
+    Ok(Json(TokenDto { token }))
+}
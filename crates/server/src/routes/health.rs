@@ -0,0 +1,87 @@
+//! Probes for the container orchestrator. They live outside `/api`, need no
+//! identity headers and are not rate limited.
+
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use tokio::time::timeout;
+use tracing::{error, warn};
+use wr_database::Database;
+
+/// How long the database may take to answer before the server is not ready.
+const DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The process is up and serving requests.
+pub async fn healthz() -> impl IntoResponse {
+    "ok"
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    version: String,
+    database: DatabaseCheck,
+}
+
+#[derive(Serialize)]
+struct DatabaseCheck {
+    ok: bool,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Migrations of this build that are not applied.
+    pending_migrations: Vec<String>,
+}
+
+/// Ready when the database answers within [`DB_TIMEOUT`] and its schema is
+/// up to date, `503 Service Unavailable` otherwise.
+pub async fn readyz(
+    State(ref db): State<Database>,
+    State(version): State<String>,
+) -> impl IntoResponse {
+    let database = check_database(db).await;
+    let ready = database.ok && database.pending_migrations.is_empty();
+    if !ready {
+        warn!(
+            error = ?database.error,
+            pending_migrations = ?database.pending_migrations,
+            "readiness check failed"
+        );
+    }
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = Readiness {
+        ready,
+        version,
+        database,
+    };
+    (status, Json(body))
+}
+
+async fn check_database(db: &Database) -> DatabaseCheck {
+    let start = Instant::now();
+    let result = timeout(DB_TIMEOUT, async {
+        db.conn.ping().await?;
+        db.pending_migrations().await
+    })
+    .await;
+    let (pending_migrations, error) = match result {
+        Ok(Ok(pending)) => (pending, None),
+        // the probe is unauthenticated, keep driver errors in the log
+        Ok(Err(e)) => {
+            error!("database check failed: {e}");
+            (vec![], Some("database error".to_string()))
+        }
+        Err(_) => (vec![], Some(format!("no answer within {DB_TIMEOUT:?}"))),
+    };
+    DatabaseCheck {
+        ok: error.is_none(),
+        latency_ms: start.elapsed().as_millis(),
+        error,
+        pending_migrations,
+    }
+}
@@ -0,0 +1,497 @@
+//! Bulk user import.
+//!
+//! Admins upload a user list either as a raw request body or as a multipart
+//! file, in JSON (an array of objects) or CSV (with a header row). Every row is
+//! validated on its own and users are upserted by email. The whole import runs
+//! in a single transaction: if any row fails, or `?dry_run=true` is given, the
+//! transaction is rolled back and only the per-row report is returned.
+//! Uploads are capped at [`MAX_IMPORT_BYTES`].
+
+use std::collections::HashSet;
+
+use axum::{
+    extract::{multipart::MultipartError, FromRequest, Multipart, Request, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use http_body_util::LengthLimitError;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+use wr_database::{user, Database, TransactionTrait};
+
+use super::extract::{Json, Query};
+use crate::{audit::Auditor, traits::ErrorBody, ResponseError};
+
+/// Largest upload accepted, a few thousand users take well under this.
+pub const MAX_IMPORT_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Validate only, never commit.
+    pub dry_run: Option<bool>,
+}
+
+/// One user row of an import file. Unknown columns are ignored so that the
+/// output of `GET /api/user` can be fed back in as-is.
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    name: Option<String>,
+    email: Option<String>,
+    direction: Option<String>,
+    level: Option<i32>,
+    is_hidden: Option<bool>,
+    is_admin: Option<bool>,
+    is_banned: Option<bool>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum RowStatus {
+    Created,
+    Updated,
+    Skipped,
+    Error,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RowReport {
+    /// 1-based index of the row in the uploaded data, not counting the CSV
+    /// header.
+    row: usize,
+    name: Option<String>,
+    email: Option<String>,
+    status: RowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportReport {
+    dry_run: bool,
+    committed: bool,
+    created: usize,
+    updated: usize,
+    skipped: usize,
+    errors: usize,
+    rows: Vec<RowReport>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+        match mime.to_ascii_lowercase().as_str() {
+            "application/json" | "text/json" => Some(Format::Json),
+            "text/csv" | "application/csv" | "application/vnd.ms-excel" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    fn from_file_name(name: &str) -> Option<Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        match ext.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    /// Last resort when neither the content type nor the file name helps.
+    fn sniff(data: &[u8]) -> Self {
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'[') => Format::Json,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// Read the upload from the request, either the first file field of a
+/// multipart form or the raw body.
+async fn read_upload(req: Request) -> Result<(Format, Vec<u8>), ResponseError> {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .map(|v| v.to_str())
+        .transpose()?
+        .unwrap_or_default()
+        .to_owned();
+
+    if content_type.starts_with("multipart/form-data") {
+        let mut multipart = Multipart::from_request(req, &())
+            .await
+            .map_err(|e| ResponseError::BadRequest(e.body_text()))?;
+        while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+            let Some(file_name) = field.file_name().map(str::to_owned) else {
+                continue;
+            };
+            let format = field
+                .content_type()
+                .and_then(Format::from_mime)
+                .or_else(|| Format::from_file_name(&file_name));
+            let data = field.bytes().await.map_err(multipart_error)?;
+            let format = format.unwrap_or_else(|| Format::sniff(&data));
+            return Ok((format, data.to_vec()));
+        }
+        return Err(ResponseError::BadRequest(
+            "no file found in multipart form".to_string(),
+        ));
+    }
+
+    let data = axum::body::to_bytes(req.into_body(), MAX_IMPORT_BYTES)
+        .await
+        .map_err(|e| {
+            let e = e.into_inner();
+            if e.is::<LengthLimitError>() {
+                too_large()
+            } else {
+                ResponseError::BadRequest(e.to_string())
+            }
+        })?;
+    let format = Format::from_mime(&content_type).unwrap_or_else(|| Format::sniff(&data));
+    Ok((format, data.to_vec()))
+}
+
+fn too_large() -> ResponseError {
+    ResponseError::PayloadTooLarge(format!(
+        "uploads are limited to {} MiB",
+        MAX_IMPORT_BYTES / 1024 / 1024
+    ))
+}
+
+/// Multipart forms are capped by the `DefaultBodyLimit` of the route.
+fn multipart_error(e: MultipartError) -> ResponseError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        too_large()
+    } else {
+        ResponseError::BadRequest(e.body_text())
+    }
+}
+
+/// Parse the upload into rows. A malformed row only fails itself, a malformed
+/// document fails the whole request.
+fn parse_rows(
+    format: Format,
+    data: &[u8],
+) -> Result<Vec<Result<ImportRow, String>>, ResponseError> {
+    match format {
+        Format::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_slice(data)
+                .map_err(|e| ResponseError::BadRequest(format!("invalid json: {e}")))?;
+            Ok(values
+                .into_iter()
+                .map(|v| serde_json::from_value(v).map_err(|e| e.to_string()))
+                .collect())
+        }
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(data);
+            // make sure the header row itself is readable before going on
+            reader
+                .headers()
+                .map_err(|e| ResponseError::BadRequest(format!("invalid csv: {e}")))?;
+            Ok(reader
+                .deserialize::<ImportRow>()
+                .map(|r| r.map_err(|e| e.to_string()))
+                .collect())
+        }
+    }
+}
+
+/// Normalize a row and check the fields that do not need the database.
+fn validate_row(
+    row: ImportRow,
+    seen_names: &mut HashSet<String>,
+    seen_emails: &mut HashSet<String>,
+) -> Result<(String, String, ImportRow), String> {
+    let name = row.name.as_deref().map(str::trim).unwrap_or_default();
+    if name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    let email = row
+        .email
+        .as_deref()
+        .map(str::trim)
+        .unwrap_or_default()
+        .to_lowercase();
+    if email.is_empty() {
+        return Err("email is required".to_string());
+    }
+    match email.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') => {}
+        _ => return Err(format!("invalid email: {email}")),
+    }
+    if row.level.is_some_and(|l| l < 0) {
+        return Err("level must not be negative".to_string());
+    }
+    if !seen_names.insert(name.to_string()) {
+        return Err(format!("duplicate name in upload: {name}"));
+    }
+    if !seen_emails.insert(email.clone()) {
+        return Err(format!("duplicate email in upload: {email}"));
+    }
+    Ok((name.to_string(), email, row))
+}
+
+/// Apply the row on top of an existing user, keeping fields the row leaves
+/// out untouched.
+fn merge_row(existing: &user::Model, name: String, row: ImportRow) -> user::Model {
+    user::Model {
+        name,
+        direction: row.direction.or_else(|| existing.direction.clone()),
+        level: row.level.unwrap_or(existing.level),
+        is_hidden: row.is_hidden.unwrap_or(existing.is_hidden),
+        is_admin: row.is_admin.unwrap_or(existing.is_admin),
+        is_banned: row.is_banned.unwrap_or(existing.is_banned),
+        ..existing.clone()
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/import",
+    tag = "user",
+    params(ImportQuery),
+    request_body(
+        description = "A JSON array or a CSV file, as raw body or the first file of a multipart form",
+        content(
+            ("application/json"),
+            ("text/csv"),
+            ("multipart/form-data"),
+        ),
+    ),
+    responses(
+        (status = 200, body = ImportReport),
+        (status = 400, body = ErrorBody),
+        (status = 403, description = "Missing `manage_users`", body = ErrorBody),
+        (status = 413, description = "Larger than 4 MiB", body = ErrorBody),
+        (status = 422, description = "Some rows are invalid, nothing was committed", body = ImportReport),
+    ),
+)]
+pub async fn import_users(
+    State(ref db): State<Database>,
+    Extension(actor): Extension<user::Model>,
+    auditor: Auditor,
+    Query(query): Query<ImportQuery>,
+    req: Request,
+) -> Result<impl IntoResponse, ResponseError> {
+    let dry_run = query.dry_run.unwrap_or(false);
+    let (format, data) = read_upload(req).await?;
+    let rows = parse_rows(format, &data)?;
+
+    let txn = db.conn.begin().await?;
+    let mut seen_names = HashSet::new();
+    let mut seen_emails = HashSet::new();
+    let mut reports = Vec::with_capacity(rows.len());
+
+    for (idx, row) in rows.into_iter().enumerate() {
+        let mut report = RowReport {
+            row: idx + 1,
+            name: None,
+            email: None,
+            status: RowStatus::Error,
+            message: None,
+        };
+        let checked = row.and_then(|row| {
+            report.name = row.name.clone();
+            report.email = row.email.clone();
+            validate_row(row, &mut seen_names, &mut seen_emails)
+        });
+        let (name, email, row) = match checked {
+            Ok(v) => v,
+            Err(message) => {
+                report.message = Some(message);
+                reports.push(report);
+                continue;
+            }
+        };
+        report.name = Some(name.clone());
+        report.email = Some(email.clone());
+
+        let existing = user::get_by_email(&txn, &email).await?;
+        if let Some(owner) = user::get_by_name(&txn, &name).await? {
+            if existing.as_ref().map(|u| u.id) != Some(owner.id) {
+                report.message = Some(format!("name is already used by user {}", owner.id));
+                reports.push(report);
+                continue;
+            }
+        }
+
+        let result = match existing {
+            None => {
+                let model = user::Model {
+                    name,
+                    email: Some(email),
+                    direction: row.direction,
+                    level: row.level.unwrap_or_default(),
+                    is_hidden: row.is_hidden.unwrap_or(false),
+                    is_admin: row.is_admin.unwrap_or(false),
+                    is_banned: row.is_banned.unwrap_or(false),
+                    ..Default::default()
+                };
+                user::create(&txn, model).await.map(|_| RowStatus::Created)
+            }
+            Some(existing) => {
+                let merged = merge_row(&existing, name, row);
+                if merged == existing {
+                    Ok(RowStatus::Skipped)
+                } else {
+                    user::update(&txn, merged).await.map(|_| RowStatus::Updated)
+                }
+            }
+        };
+        match result {
+            Ok(status) => report.status = status,
+            Err(e) => report.message = Some(e.to_string()),
+        }
+        reports.push(report);
+    }
+
+    let count = |status| reports.iter().filter(|r| r.status == status).count();
+    let (created, updated, skipped, errors) = (
+        count(RowStatus::Created),
+        count(RowStatus::Updated),
+        count(RowStatus::Skipped),
+        count(RowStatus::Error),
+    );
+    let committed = !dry_run && errors == 0;
+    if committed {
+        txn.commit().await?;
+        let changed: Vec<_> = reports
+            .iter()
+            .filter(|r| matches!(r.status, RowStatus::Created | RowStatus::Updated))
+            .map(|r| serde_json::json!({ "email": r.email, "status": r.status }))
+            .collect();
+        auditor
+            .record("user.import", None, None, Some(changed.into()))
+            .await;
+    } else {
+        txn.rollback().await?;
+    }
+
+    info!(
+        actor = %actor.name,
+        dry_run,
+        committed,
+        created,
+        updated,
+        skipped,
+        errors,
+        "import_users finished"
+    );
+
+    let status = if errors > 0 {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+    Ok((
+        status,
+        Json(ImportReport {
+            dry_run,
+            committed,
+            created,
+            updated,
+            skipped,
+            errors,
+            rows: reports,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+
+    use super::*;
+
+    fn validate_all(rows: Vec<Result<ImportRow, String>>) -> Vec<Result<String, String>> {
+        let (mut names, mut emails) = (HashSet::new(), HashSet::new());
+        rows.into_iter()
+            .map(|row| {
+                row.and_then(|row| validate_row(row, &mut names, &mut emails))
+                    .map(|(_, email, _)| email)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_json_and_csv() {
+        let json = br#"[
+            {"name": "alice", "email": "Alice@Example.com", "level": 1, "id": 3},
+            {"name": "bob", "email": "bob@example.com", "level": "high"},
+            {"name": "carol", "email": "carol@example.com"}
+        ]"#;
+        let rows = validate_all(parse_rows(Format::Json, json).unwrap());
+        assert_eq!(rows[0], Ok("alice@example.com".to_string()));
+        assert!(rows[1].is_err());
+        assert_eq!(rows[2], Ok("carol@example.com".to_string()));
+
+        let csv = b"name,email,level,is_hidden\n\
+            alice, alice@example.com ,1,true\n\
+            bob,bob@example.com,high,false\n\
+            carol,carol@example.com,,\n";
+        let rows = validate_all(parse_rows(Format::Csv, csv).unwrap());
+        assert_eq!(rows[0], Ok("alice@example.com".to_string()));
+        assert!(rows[1].is_err());
+        assert_eq!(rows[2], Ok("carol@example.com".to_string()));
+
+        assert!(parse_rows(Format::Json, b"{\"name\": \"alice\"}").is_err());
+    }
+
+    #[test]
+    fn duplicates_within_an_upload_fail_the_later_row() {
+        let json = br#"[
+            {"name": "alice", "email": "alice@example.com"},
+            {"name": "alice", "email": "other@example.com"},
+            {"name": "bob", "email": "ALICE@example.com"},
+            {"name": "", "email": "carol@example.com"},
+            {"name": "dave", "email": "dave"}
+        ]"#;
+        let rows = validate_all(parse_rows(Format::Json, json).unwrap());
+        assert!(rows[0].is_ok());
+        assert_eq!(rows[1], Err("duplicate name in upload: alice".to_string()));
+        assert_eq!(
+            rows[2],
+            Err("duplicate email in upload: alice@example.com".to_string())
+        );
+        assert_eq!(rows[3], Err("name is required".to_string()));
+        assert_eq!(rows[4], Err("invalid email: dave".to_string()));
+    }
+
+    #[test]
+    fn merging_keeps_what_the_row_leaves_out() {
+        let existing = user::Model {
+            id: 4,
+            name: "alice".into(),
+            direction: Some("web".into()),
+            level: 1,
+            is_hidden: true,
+            ..Default::default()
+        };
+        let row: ImportRow = serde_json::from_str(r#"{"level": 2}"#).unwrap();
+        let merged = merge_row(&existing, "alice".into(), row);
+        assert_eq!(merged.level, 2);
+        assert_eq!(merged.direction.as_deref(), Some("web"));
+        assert!(merged.is_hidden);
+        assert_eq!(merged.id, 4);
+    }
+
+    #[tokio::test]
+    async fn oversized_uploads_are_rejected() {
+        let req = Request::builder()
+            .header(CONTENT_TYPE, "text/csv")
+            .body(Body::from(vec![b'a'; MAX_IMPORT_BYTES + 1]))
+            .unwrap();
+        let err = read_upload(req).await.unwrap_err();
+        assert!(matches!(err, ResponseError::PayloadTooLarge(_)), "{err}");
+    }
+}
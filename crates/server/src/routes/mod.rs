@@ -1,90 +1,161 @@
-use std::{net::IpAddr, time::Duration};
-
 use axum::{
-    body::Body,
-    extract::{Query, Request, State},
+    extract::{DefaultBodyLimit, State},
+    http::{header::STRICT_TRANSPORT_SECURITY, HeaderValue},
     middleware,
-    response::{IntoResponse, Response},
-    routing::{get, patch, post},
-    Extension, Json, Router,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
 use chrono::{Datelike, Duration as ChronoDuration, Utc, DateTime};
 use serde::{Deserialize, Serialize};
 use tower_http::{
     services::{ServeDir, ServeFile},
+    set_header::SetResponseHeaderLayer,
     trace::TraceLayer,
 };
-use tracing::{debug, debug_span, Span};
-use wr_database::{report, user, Database};
-use wr_database::report::ExModel;
+use utoipa::{IntoParams, ToSchema};
+use wr_database::{config, report, user, Database};
+use wr_database::report::{Audience, ExModel, Visibility};
 
+use self::extract::{Json, Path, Query};
 use crate::{
-    middleware::{auth, data, forwarded},
-    traits::GlobalState,
+    audit::Auditor,
+    events::{Event, EventBus, LikeEvent, ReportEvent, StatusEvent},
+    metrics,
+    middleware::{
+        access_log, auth, data,
+        forwarded::{self, PublicUrl},
+        rate_limit::{self, Quota, RateLimiter, RateLimits},
+        request_id,
+    },
+    policy::{self, Authorize, Permission, UserDto},
+    traits::{ErrorBody, GlobalState},
     ResponseError,
 };
 
+mod audit;
+mod events;
+mod extract;
+mod feed_token;
+mod health;
+mod import;
+pub mod openapi;
+mod stats;
+mod v2;
+
 pub async fn initialize(state: GlobalState) -> anyhow::Result<Router> {
-    let api_router = construct_router(&state);
-    let serve_dir = ServeDir::new(std::env::var("WR_STATIC")?)
+    let api_router = construct_router(&state, &state.settings.rate_limit);
+    let static_dir = &state.settings.server.static_dir;
+    let serve_dir = ServeDir::new(static_dir)
         .precompressed_gzip()
-        .not_found_service(ServeFile::new(format!(
-            "{}/index.html",
-            std::env::var("WR_STATIC")?
-        )));
+        .not_found_service(ServeFile::new(static_dir.join("index.html")));
     let router = Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
         .nest("/api", api_router)
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(|request: &Request<Body>| {
-                    let ip = forwarded::get_client_ip(request)
-                        .unwrap_or(IpAddr::V4("0.0.0.0".parse().unwrap()));
-                    debug_span!("http",
-                        from = %ip.to_string(),
-                        method = %request.method(),
-                        uri = %request.uri().path(),
-                    )
-                })
+                .make_span_with(access_log::make_span)
                 .on_request(())
-                .on_response(|response: &Response, latency: Duration, _span: &Span| {
-                    debug!("[{}] in {}ms", response.status(), latency.as_millis());
-                }),
+                .on_response(access_log::on_response),
         )
-        .fallback_service(serve_dir)
-        .with_state::<()>(state);
-    Ok(router)
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(request_id::propagate))
+        .layer(middleware::from_fn_with_state(
+            state.settings.server.trusted_proxies.clone(),
+            forwarded::resolve,
+        ))
+        .fallback_service(serve_dir);
+    let router = match state.settings.server.tls.as_ref().and_then(|t| t.hsts_max_age) {
+        Some(max_age) => router.layer(SetResponseHeaderLayer::overriding(
+            STRICT_TRANSPORT_SECURITY,
+            HeaderValue::try_from(format!("max-age={}", max_age.as_secs()))?,
+        )),
+        None => router,
+    };
+    Ok(router.with_state::<()>(state))
 }
 
-pub fn construct_router(state: &GlobalState) -> Router<GlobalState> {
+pub fn construct_router(state: &GlobalState, limits: &RateLimits) -> Router<GlobalState> {
+    // Rate limit a route group. Behind login the limit applies per user,
+    // elsewhere per client IP.
+    let limit = |router: Router<GlobalState>, group, quota: Option<Quota>| match quota {
+        Some(quota) => router.route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(group, quota),
+            rate_limit::rate_limit,
+        )),
+        None => router,
+    };
+
     // public routes (no auth required)
-    let public = Router::new().route("/{id}/feed/", get(get_user_feed));
+    let public = Router::new()
+        .route("/{id}/feed/", get(get_user_feed))
+        .route("/{id}/feed/public", get(get_public_user_feed))
+        .route("/public/report/{id}", get(get_public_report));
+    let public = limit(public, "feed", limits.feed)
+        .route("/openapi.json", get(openapi::get_openapi));
 
-    // protected routes (may apply middleware)
-    // Admin-only routes: put under a small admin router that will be merged into protected
-    let admin_router = Router::new()
-        .route("/import", post(import_users))
-        .route("/user", patch(modify_user))
-        .route_layer(middleware::from_fn_with_state(
-            state.clone(),
-            auth::admin_required,
-        ));
+    // Routes that need a specific permission on top of a logged-in user. Each
+    // group declares its permission with a `permission_required` layer.
+    let require =
+        |permission| middleware::from_fn_with_state(permission, auth::permission_required);
+    let manage_users = Router::new()
+        .route(
+            "/import",
+            post(import::import_users).layer(DefaultBodyLimit::max(import::MAX_IMPORT_BYTES)),
+        )
+        .route("/user/{id}/ban", post(ban_user))
+        .route("/user/{id}/unban", post(unban_user))
+        .route_layer(require(Permission::ManageUsers));
+    let manage_config = Router::new()
+        .route("/config", put(update_config))
+        .route_layer(require(Permission::ManageConfig));
+    let view_audit_log = Router::new()
+        .route("/audit", get(audit::get_audit_logs))
+        .route_layer(require(Permission::ViewAuditLog));
+    let view_stats = Router::new()
+        .route("/stats/overview", get(stats::get_overview))
+        .route("/stats/weeks", get(stats::get_weeks))
+        .route("/stats/user/{id}", get(stats::get_user_stats))
+        .route_layer(require(Permission::ViewStats));
+    let submit_report = Router::new()
+        .route("/report", post(handle_submit))
+        .route_layer(require(Permission::SubmitReport));
+    let react = Router::new()
+        // like a report: POST /api/report/{id}/like
+        .route("/report/{id}/like", post(like_report))
+        // unlike a report: POST /api/report/{id}/unlike
+        .route("/report/{id}/unlike", post(unlike_report))
+        .route_layer(require(Permission::React));
+    let react = limit(react, "react", limits.react);
 
-    // Protected routes: first construct routes (including merging admin routes),
-    // then apply the prepare_user_info middleware so it attaches Extension<user::Model>
-    // for all protected endpoints (including admin routes which still have their
-    // own admin_required middleware).
+    // Protected routes: first construct routes (including merging the
+    // permission groups above), then apply the prepare_user_info middleware so
+    // it attaches Extension<user::Model> for all protected endpoints before
+    // any permission check runs. `react` has its own limit and is merged after
+    // the "read" one so likes are not charged to both.
     let protected = Router::new()
-        .merge(admin_router)
-        .route("/user", get(get_user))
-        .route("/report", get(get_report).post(handle_submit))
-    // like a report: POST /api/report/{id}/like
-    .route("/report/{id}/like", post(like_report))
-    // unlike a report: POST /api/report/{id}/unlike
-    .route("/report/{id}/unlike", post(unlike_report))
+        .merge(manage_users)
+        .merge(manage_config)
+        .merge(view_audit_log)
+        .merge(view_stats)
+        .merge(submit_report)
+        .route("/user", get(get_user).patch(modify_user))
+        .route("/report", get(get_report))
         .route("/self", get(get_self_info))
         .route("/ping", get(ping))
-    .route("/self/feed_token", get(get_or_create_feed_token).post(regenerate_feed_token))
+        .route("/self/feed_token", post(feed_token::regenerate_token))
+        .route(
+            "/self/feed_tokens",
+            get(feed_token::list_tokens).post(feed_token::create_token),
+        )
+        .route("/self/feed_tokens/{id}", delete(feed_token::revoke_token))
         .route("/status", get(get_status))
+        .route("/events", get(events::subscribe))
+        .route("/config", get(get_config))
+        .nest("/v2", v2::router());
+    let protected = limit(protected, "read", limits.read)
+        .merge(react)
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             data::prepare_user_info,
@@ -93,17 +164,34 @@ pub fn construct_router(state: &GlobalState) -> Router<GlobalState> {
     public.merge(protected)
 }
 
+#[utoipa::path(
+    get,
+    path = "/ping",
+    tag = "status",
+    responses((status = 200, description = "Logged in", body = String, content_type = "text/plain")),
+)]
 async fn ping() -> impl IntoResponse {
     "pong"
 }
 
-#[derive(Serialize)]
-struct StatusResponse {
-    submitted: Vec<String>,
-    pending: Vec<String>,
+#[derive(Serialize, ToSchema)]
+pub(crate) struct StatusResponse {
+    pub submitted: Vec<String>,
+    pub pending: Vec<String>,
 }
 
+/// Who has and has not submitted a report this week.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "status",
+    responses((status = 200, body = StatusResponse)),
+)]
 async fn get_status(State(ref db): State<Database>) -> Result<impl IntoResponse, ResponseError> {
+    Ok(Json(week_status(db).await?))
+}
+
+pub(crate) async fn week_status(db: &Database) -> Result<StatusResponse, ResponseError> {
     let users = user::get_list(&db.conn, false).await?;
     let now = Utc::now();
     let next_sunday = if now.weekday() != chrono::Weekday::Sun {
@@ -113,7 +201,7 @@ async fn get_status(State(ref db): State<Database>) -> Result<impl IntoResponse,
     };
     let edge =
         next_sunday.year() * 10_000 + next_sunday.month() as i32 * 100 + next_sunday.day() as i32;
-    let reports = report::get_week_list(&db.conn, edge).await?;
+    let reports = report::get_week_list(&db.conn, edge, &Audience::System).await?;
     let mut submitted = vec![];
     let mut pending = vec![];
     for user in users {
@@ -124,101 +212,405 @@ async fn get_status(State(ref db): State<Database>) -> Result<impl IntoResponse,
             pending.push(user.name.clone());
         }
     }
-    Ok(Json(StatusResponse { submitted, pending }))
+    Ok(StatusResponse { submitted, pending })
+}
+
+// Term configuration. `skip_weeks` is stored as a JSON array string, like
+// report likes, and exposed as an array.
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ConfigDto {
+    pub begin_week: i32,
+    #[serde(default)]
+    pub skip_weeks: Vec<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/config",
+    tag = "config",
+    responses((status = 200, body = ConfigDto)),
+)]
+async fn get_config(State(ref db): State<Database>) -> Result<impl IntoResponse, ResponseError> {
+    let dto = match config::get(&db.conn).await? {
+        Some(c) => ConfigDto {
+            begin_week: c.begin_week,
+            skip_weeks: c
+                .skip_weeks
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default(),
+        },
+        None => ConfigDto {
+            begin_week: 0,
+            skip_weeks: vec![],
+        },
+    };
+    Ok(Json(dto))
+}
+
+#[utoipa::path(
+    put,
+    path = "/config",
+    tag = "config",
+    request_body = ConfigDto,
+    responses(
+        (status = 200, body = ConfigDto),
+        (status = 403, description = "Missing `manage_config`", body = ErrorBody),
+    ),
+)]
+async fn update_config(
+    State(ref db): State<Database>,
+    auditor: Auditor,
+    Json(form): Json<ConfigDto>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let before = config::get(&db.conn).await?.unwrap_or_default();
+    let skip_weeks = serde_json::to_string(&form.skip_weeks)?;
+    let after = config::save(&db.conn, form.begin_week, Some(skip_weeks)).await?;
+    auditor
+        .record_change("config.update", None, &before, &after)
+        .await;
+    Ok(Json(form))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct UserQuery {
+    /// Fetch a single user instead of the list.
     pub id: Option<i32>,
+    /// Include hidden users in the list, needs `read_hidden_users`.
     pub with_hidden: Option<bool>,
 }
 
-async fn import_users(
+/// A single user, or `null` if it does not exist, when `id` is given,
+/// otherwise the user list.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum UserQueryResponse {
+    One(Option<UserDto>),
+    List(Vec<UserDto>),
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ModifyUserForm {
+    pub id: i32,
+    #[serde(flatten)]
+    pub patch: user::UserPatch,
+}
+
+/// Fields a regular user may change on their own account, everything else
+/// needs `Permission::ManageUsers`.
+const SELF_EDITABLE_FIELDS: &[&str] = &["direction", "default_visibility"];
+
+// Partially update a user. User managers may change any field of any user,
+// everyone else may only change the self-editable fields of their own account.
+#[utoipa::path(
+    patch,
+    path = "/user",
+    tag = "user",
+    request_body = ModifyUserForm,
+    responses(
+        (status = 200, body = UserDto),
+        (status = 400, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn modify_user(
     State(ref db): State<Database>,
-    Json(users): Json<Vec<user::Model>>,
+    Extension(current_user): Extension<user::Model>,
+    auditor: Auditor,
+    Json(ModifyUserForm { id, patch }): Json<ModifyUserForm>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    user::create_list(&db.conn, users).await?;
+    check_patch_allowed(&current_user, id, &patch)?;
+    if id == current_user.id && patch.is_admin == Some(false) {
+        return Err(ResponseError::BadRequest(
+            "cannot revoke your own admin role".to_string(),
+        ));
+    }
+    if patch.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+        return Err(ResponseError::BadRequest("name must not be empty".to_string()));
+    }
+    if patch.level.is_some_and(|l| l < 0) {
+        return Err(ResponseError::BadRequest("level must not be negative".to_string()));
+    }
+    if patch.role == Some(user::Role::Admin) {
+        return Err(ResponseError::BadRequest(
+            "set `is_admin` to make an admin".to_string(),
+        ));
+    }
+
+    let Some(before) = user::get(&db.conn, id).await? else {
+        return Err(ResponseError::NotFound("user not found".to_string()));
+    };
+    let after = user::patch(&db.conn, id, patch).await?;
+    auditor
+        .record_change("user.modify", Some(format!("user:{id}")), &before, &after)
+        .await;
+    Ok(Json(policy::project_user(&current_user, after)))
+}
+
+/// Whether `current_user` may apply `patch` to user `id`, see
+/// [`SELF_EDITABLE_FIELDS`].
+fn check_patch_allowed(
+    current_user: &user::Model,
+    id: i32,
+    patch: &user::UserPatch,
+) -> Result<(), ResponseError> {
+    if current_user.can(Permission::ManageUsers) {
+        return Ok(());
+    }
+    if id != current_user.id {
+        return Err(ResponseError::Forbidden(
+            "admin required".to_string(),
+            format!("{} tried to modify user {id}", current_user.name),
+        ));
+    }
+    if let Some(field) = patch
+        .fields()
+        .into_iter()
+        .find(|f| !SELF_EDITABLE_FIELDS.contains(f))
+    {
+        return Err(ResponseError::Forbidden(
+            format!("only user managers can change `{field}`"),
+            format!(
+                "{} tried to change `{field}` of themselves",
+                current_user.name
+            ),
+        ));
+    }
     Ok(())
 }
 
-async fn modify_user(
+#[derive(Deserialize, ToSchema)]
+struct BanForm {
+    pub reason: Option<String>,
+    /// Unix timestamp (seconds) when the ban is lifted, omit for a permanent
+    /// ban.
+    pub until: Option<i64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/{id}/ban",
+    tag = "user",
+    params(("id" = i32, Path, description = "User id")),
+    request_body = BanForm,
+    responses(
+        (status = 200, body = UserDto),
+        (status = 400, body = ErrorBody),
+        (status = 403, description = "Missing `manage_users`", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn ban_user(
     State(ref db): State<Database>,
-    Json(user): Json<user::Model>,
+    Extension(current_user): Extension<user::Model>,
+    auditor: Auditor,
+    Path(id): Path<i32>,
+    Json(form): Json<BanForm>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    Ok(Json(user::update(&db.conn, user).await?))
+    if id == current_user.id {
+        return Err(ResponseError::BadRequest("cannot ban yourself".to_string()));
+    }
+    let until = form
+        .until
+        .map(|ts| {
+            DateTime::from_timestamp(ts, 0)
+                .filter(|until| *until > Utc::now())
+                .ok_or_else(|| {
+                    ResponseError::BadRequest(
+                        "`until` must be a unix timestamp in the future".to_string(),
+                    )
+                })
+        })
+        .transpose()?;
+    let Some(before) = user::get(&db.conn, id).await? else {
+        return Err(ResponseError::NotFound("user not found".to_string()));
+    };
+    let reason = form.reason.filter(|r| !r.trim().is_empty());
+    let after = user::ban(&db.conn, id, reason, until).await?;
+    auditor
+        .record_change("user.ban", Some(format!("user:{id}")), &before, &after)
+        .await;
+    Ok(Json(policy::project_user(&current_user, after)))
 }
 
+#[utoipa::path(
+    post,
+    path = "/user/{id}/unban",
+    tag = "user",
+    params(("id" = i32, Path, description = "User id")),
+    responses(
+        (status = 200, body = UserDto),
+        (status = 403, description = "Missing `manage_users`", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn unban_user(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    auditor: Auditor,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let Some(before) = user::get(&db.conn, id).await? else {
+        return Err(ResponseError::NotFound("user not found".to_string()));
+    };
+    let after = user::unban(&db.conn, id).await?;
+    auditor
+        .record_change("user.unban", Some(format!("user:{id}")), &before, &after)
+        .await;
+    Ok(Json(policy::project_user(&current_user, after)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user",
+    tag = "user",
+    params(UserQuery),
+    responses(
+        (status = 200, body = UserQueryResponse),
+        (status = 403, body = ErrorBody),
+    ),
+)]
 async fn get_user(
     State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
     Query(query): Query<UserQuery>,
 ) -> Result<impl IntoResponse, ResponseError> {
+    if query.with_hidden == Some(true) && !current_user.can(Permission::ReadHiddenUsers) {
+        return Err(ResponseError::Forbidden(
+            "permission denied".to_string(),
+            format!("{} tried to list hidden users", current_user.name),
+        ));
+    }
     match query {
         UserQuery {
             id: Some(id),
             with_hidden: _,
         } => {
-            let user = user::get(&db.conn, id).await?;
-            Ok(Json(user).into_response())
+            // hidden users look the same as missing ones to those who cannot see them
+            let user = user::get(&db.conn, id)
+                .await?
+                .filter(|u| policy::can_see_user(&current_user, u))
+                .map(|u| policy::project_user(&current_user, u));
+            Ok(Json(UserQueryResponse::One(user)))
         }
         UserQuery {
             id: None,
             with_hidden,
         } => {
-            let users = user::get_list(&db.conn, with_hidden.unwrap_or(false)).await?;
-            Ok(Json(users).into_response())
+            let users: Vec<_> = user::get_list(&db.conn, with_hidden.unwrap_or(false))
+                .await?
+                .into_iter()
+                .map(|u| policy::project_user(&current_user, u))
+                .collect();
+            Ok(Json(UserQueryResponse::List(users)))
         }
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct SubmitForm {
     pub content: String,
+    /// Defaults to the current visibility when editing, and to the user's
+    /// default visibility for a new report.
+    pub visibility: Option<Visibility>,
 }
 
+/// Submit or edit this week's report.
+#[utoipa::path(
+    post,
+    path = "/report",
+    tag = "report",
+    request_body = SubmitForm,
+    responses(
+        (status = 200, body = report::Model),
+        (status = 400, description = "Not Sunday (`submission_window_closed`)", body = ErrorBody),
+        (status = 403, description = "Missing `submit_report`", body = ErrorBody),
+    ),
+)]
 async fn handle_submit(
     State(ref db): State<Database>,
+    State(events): State<EventBus>,
     Extension(user): Extension<user::Model>,
     Json(form): Json<SubmitForm>,
 ) -> Result<impl IntoResponse, ResponseError> {
     let date = Utc::now();
     if date.weekday() != chrono::Weekday::Sun {
-        return Err(ResponseError::BadRequest("Only Sunday".to_string()));
+        return Err(ResponseError::SubmissionWindowClosed);
     }
     let week = date.year() * 10_000 + date.month() as i32 * 100 + date.day() as i32;
     let report = report::get(&db.conn, user.id, week).await?;
     if let Some(report) = report {
-        Ok(Json(
-            report::update(
-                &db.conn,
-                report::Model {
-                    content: Some(form.content),
-                    ..report
-                },
-            )
-            .await?,
-        ))
+        let report = report::update(
+            &db.conn,
+            report::Model {
+                content: Some(form.content),
+                visibility: form.visibility.unwrap_or(report.visibility),
+                ..report
+            },
+        )
+        .await?;
+        events.publish(Event::ReportUpdated(ReportEvent::new(&report, &user)));
+        Ok(Json(report))
     } else {
-        Ok(Json(
-            report::create(&db.conn, user.id, week, form.content).await?,
-        ))
+        let visibility = form.visibility.unwrap_or(user.default_visibility);
+        let report = report::create(&db.conn, user.id, week, form.content, visibility).await?;
+        events.publish(Event::ReportSubmitted(ReportEvent::new(&report, &user)));
+        // hidden users are not part of the status
+        if !user.is_hidden {
+            match week_status(db).await {
+                Ok(StatusResponse { submitted, pending }) => {
+                    events.publish(Event::StatusChanged(StatusEvent { submitted, pending }))
+                }
+                Err(e) => tracing::error!("failed to publish the week's status: {e}"),
+            }
+        }
+        Ok(Json(report))
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/self",
+    tag = "user",
+    responses((status = 200, body = UserDto)),
+)]
 async fn get_self_info(
     Extension(user): Extension<user::Model>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    Ok(Json(user))
+    Ok(Json(policy::project_user(&user, user.clone())))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ReportQuery {
+    /// Author id.
     pub user: Option<i32>,
+    /// Week, as the `yyyymmdd` date of its Sunday.
     pub week: Option<i32>,
 }
 
+/// Depends on the query: with `user` and `week` a single report (or `null`),
+/// with `user` the author's reports, with `week` the week's reports, and
+/// otherwise the index as a `[users, reports]` pair.
+///
+/// Kept for the web client, new code should use the `/v2` routes.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum ReportQueryResponse {
+    Report(Option<ExReportDto>),
+    UserReports(Vec<ReportDto>),
+    WeekReports(Vec<ExReportDto>),
+    Index(IndexDto),
+}
+
+/// Users and reports of the index table, serialized as a pair.
+#[derive(Serialize, ToSchema)]
+struct IndexDto(Vec<UserDto>, Vec<ReportDto>);
+
 // DTOs used by server responses: keep date serialization consistent and
 // expose `likes` as an array instead of the DB's JSON string.
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ReportDto {
     pub id: i32,
     pub author_id: i32,
@@ -226,10 +618,12 @@ struct ReportDto {
     pub content: Option<String>,
     pub likes: Option<Vec<String>>,
     #[serde(with = "chrono::serde::ts_seconds")]
+    #[schema(value_type = i64)]
     pub date: DateTime<Utc>,
+    pub visibility: Visibility,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ExReportDto {
     pub id: i32,
     pub author_id: i32,
@@ -238,7 +632,9 @@ struct ExReportDto {
     pub content: Option<String>,
     pub likes: Option<Vec<String>>,
     #[serde(with = "chrono::serde::ts_seconds")]
+    #[schema(value_type = i64)]
     pub date: DateTime<Utc>,
+    pub visibility: Visibility,
 }
 
 fn parse_likes_field(s: &Option<String>) -> Option<Vec<String>> {
@@ -256,6 +652,7 @@ fn model_to_dto(m: report::Model) -> ReportDto {
         content: m.content,
         likes: parse_likes_field(&m.likes),
         date: m.date,
+        visibility: m.visibility,
     }
 }
 
@@ -268,62 +665,91 @@ fn exmodel_to_dto(m: ExModel) -> ExReportDto {
         content: m.content,
         likes: parse_likes_field(&m.likes),
         date: m.date,
+        visibility: m.visibility,
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/report",
+    tag = "report",
+    params(ReportQuery),
+    responses((status = 200, body = ReportQueryResponse)),
+)]
 async fn get_report(
     State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
     Query(query): Query<ReportQuery>,
 ) -> Result<impl IntoResponse, ResponseError> {
+    let audience = policy::audience(&current_user);
     match query {
         ReportQuery {
             // return the report content
             user: Some(user),
             week: Some(week),
         } => {
-            let report = report::get_ex(&db.conn, user, week).await?;
-            let dto = report.map(|r| exmodel_to_dto(r));
-            Ok(Json(dto).into_response())
+            let report = report::get_ex(&db.conn, user, week, &audience).await?;
+            let dto = report.map(exmodel_to_dto);
+            Ok(Json(ReportQueryResponse::Report(dto)))
         }
         ReportQuery {
             // return user's report list
             user: Some(user),
             week: None,
         } => {
-            let reports = report::get_user_list(&db.conn, user).await?;
+            let reports = report::get_user_list(&db.conn, user, &audience).await?;
             let dtos: Vec<ReportDto> = reports.into_iter().map(model_to_dto).collect();
-            Ok(Json(dtos).into_response())
+            Ok(Json(ReportQueryResponse::UserReports(dtos)))
         }
         ReportQuery {
             // return week's report list
             user: None,
             week: Some(week),
         } => {
-            let reports = report::get_week_list(&db.conn, week).await?;
+            let reports = report::get_week_list(&db.conn, week, &audience).await?;
             let dtos: Vec<ExReportDto> = reports.into_iter().map(exmodel_to_dto).collect();
-            Ok(Json(dtos).into_response())
+            Ok(Json(ReportQueryResponse::WeekReports(dtos)))
         }
         _ =>
         // return reports for index table
         {
-            let reports = report::get_index_list(&db.conn).await?;
-            let users = user::get_list(&db.conn, false).await?;
+            let reports = report::get_index_list(&db.conn, &audience).await?;
+            let users: Vec<_> = user::get_list(&db.conn, false)
+                .await?
+                .into_iter()
+                .map(|u| policy::project_user(&current_user, u))
+                .collect();
             let dtos: Vec<ReportDto> = reports.into_iter().map(model_to_dto).collect();
-            Ok(Json((users, dtos)).into_response())
+            Ok(Json(ReportQueryResponse::Index(IndexDto(users, dtos))))
         }
     }
 }
 
-use uuid::Uuid;
-
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct FeedQuery {
+    /// Feed token of the subscriber.
     token: Option<String>,
 }
 
+/// RSS feed of a user's reports, as far as the token's owner may read them.
+#[utoipa::path(
+    get,
+    path = "/{id}/feed/",
+    tag = "feed",
+    security(()),
+    params(("id" = i32, Path, description = "Author id"), FeedQuery),
+    responses(
+        (status = 200, body = String, content_type = "application/rss+xml"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token's owner is banned", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
 async fn get_user_feed(
     State(ref db): State<Database>,
-    axum::extract::Path(id): axum::extract::Path<i32>,
+    PublicUrl(base_url): PublicUrl,
+    Path(id): Path<i32>,
     Query(query): Query<FeedQuery>,
 ) -> Result<impl IntoResponse, ResponseError> {
     let user = user::get(&db.conn, id).await?;
@@ -331,26 +757,17 @@ async fn get_user_feed(
         Some(u) => u,
         None => return Err(ResponseError::NotFound("user not found".to_string())),
     };
-    let reports = report::get_user_ex_list(&db.conn, id).await?;
 
-    // Prefer explicit public URL from env for stability (WR_PUBLIC_URL), fall back to localhost
-    let base = std::env::var("WR_PUBLIC_URL").unwrap_or_else(|_| "http://localhost".to_string());
-
-    let feed = build_rss_feed(&user.name, user.id, &reports, &base);
-
-    // check token: require token param and validate that token exists in DB
+    // check token: require token param and validate it against the stored hashes
     let token = match query.token {
         Some(t) => t,
         None => return Err(ResponseError::Unauthorized("token required".to_string())),
     };
-    let subscriber = user::get_by_feed_token(&db.conn, &token).await?;
-    let subscriber = match subscriber {
-        None => return Err(ResponseError::Unauthorized("invalid token".to_string())),
-        Some(s) => s,
-    };
+    let subscriber = feed_token::authenticate(db, &token).await?;
     // Deny access for banned subscribers
-    if subscriber.is_banned {
-        return Err(ResponseError::Unauthorized("subscriber banned".to_string()));
+    data::check_ban(&subscriber, "feed")?;
+    if !policy::can_see_user(&subscriber, &user) {
+        return Err(ResponseError::NotFound("user not found".to_string()));
     }
 
     // Record auth/logging event: who accessed whose feed and when. Do NOT log the token.
@@ -363,57 +780,103 @@ async fn get_user_feed(
         "rss feed access"
     );
 
-    // Return with proper RSS content-type
-    Ok((
+    // The feed shows what the subscriber could read in the web UI.
+    let audience = policy::audience(&subscriber);
+    let reports = report::get_user_ex_list(&db.conn, id, &audience).await?;
+    let feed = build_rss_feed(&user.name, user.id, &reports, &base_url);
+    Ok(rss_response(feed, "private, max-age=300"))
+}
+
+// Unauthenticated feed of a user's public reports.
+#[utoipa::path(
+    get,
+    path = "/{id}/feed/public",
+    tag = "feed",
+    security(()),
+    params(("id" = i32, Path, description = "Author id")),
+    responses(
+        (status = 200, body = String, content_type = "application/rss+xml"),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn get_public_user_feed(
+    State(ref db): State<Database>,
+    PublicUrl(base_url): PublicUrl,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let Some(user) = user::get(&db.conn, id).await?.filter(|u| !u.is_hidden) else {
+        return Err(ResponseError::NotFound("user not found".to_string()));
+    };
+    let reports = report::get_user_ex_list(&db.conn, id, &Audience::Anonymous).await?;
+    let feed = build_rss_feed(&user.name, user.id, &reports, &base_url);
+    Ok(rss_response(feed, "public, max-age=300"))
+}
+
+// Unauthenticated read of a single public report.
+#[utoipa::path(
+    get,
+    path = "/public/report/{id}",
+    tag = "report",
+    security(()),
+    params(("id" = i32, Path, description = "Report id")),
+    responses(
+        (status = 200, body = ExReportDto),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn get_public_report(
+    State(ref db): State<Database>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ResponseError> {
+    match report::get_ex_by_id(&db.conn, id, &Audience::Anonymous).await? {
+        Some(r) => Ok(Json(exmodel_to_dto(r))),
+        None => Err(ResponseError::NotFound("report not found".to_string())),
+    }
+}
+
+/// Return with proper RSS content-type
+fn rss_response(feed: String, cache_control: &'static str) -> impl IntoResponse {
+    (
         axum::http::StatusCode::OK,
         [
             (axum::http::header::CONTENT_TYPE, "application/rss+xml; charset=utf-8"),
             (axum::http::header::HeaderName::from_static("referrer-policy"), "no-referrer"),
-            (axum::http::header::HeaderName::from_static("cache-control"), "private, max-age=300"),
+            (axum::http::header::HeaderName::from_static("cache-control"), cache_control),
             (axum::http::header::HeaderName::from_static("x-content-type-options"), "nosniff"),
         ],
         feed,
-    ))
+    )
 }
 
-async fn get_or_create_feed_token(
-    State(ref db): State<Database>,
-    Extension(current_user): Extension<user::Model>,
-) -> Result<impl IntoResponse, ResponseError> {
-    // if user already has feed_token return it, otherwise create a new one and persist
-    if let Some(token) = current_user.feed_token.clone() {
-        return Ok(Json(serde_json::json!({ "token": token })));
-    }
-    let mut new_user = current_user.clone();
-    let token = Uuid::new_v4().to_string();
-    new_user.feed_token = Some(token.clone());
-    let updated = user::update(&db.conn, new_user).await?;
-    Ok(Json(serde_json::json!({ "token": updated.feed_token })))
-}
-
-// Regenerate (reset) the current user's feed token. Protected endpoint.
-async fn regenerate_feed_token(
-    State(ref db): State<Database>,
-    Extension(current_user): Extension<user::Model>,
-) -> Result<impl IntoResponse, ResponseError> {
-    let mut new_user = current_user.clone();
-    let token = Uuid::new_v4().to_string();
-    new_user.feed_token = Some(token.clone());
-    let updated = user::update(&db.conn, new_user).await?;
-    Ok(Json(serde_json::json!({ "token": updated.feed_token })))
+#[derive(Serialize, ToSchema)]
+struct LikesDto {
+    /// Names of the users who like the report.
+    likes: Vec<String>,
 }
 
 // Like a report. Bodyless POST. Requirements:
 // - authenticated user (provided by data::prepare_user_info middleware)
 // - cannot like own report
 // - cannot like twice (username unique)
+#[utoipa::path(
+    post,
+    path = "/report/{id}/like",
+    tag = "report",
+    params(("id" = i32, Path, description = "Report id")),
+    responses(
+        (status = 200, body = LikesDto),
+        (status = 400, description = "`cannot_like_own_report` or `already_liked`", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
 async fn like_report(
     State(ref db): State<Database>,
+    State(events): State<EventBus>,
     Extension(current_user): Extension<user::Model>,
-    axum::extract::Path(id): axum::extract::Path<i32>,
+    Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    // find report by id using database helper
-    let r = report::get_by_id(&db.conn, id).await?;
+    // find report by id using database helper, hidden from users who cannot read it
+    let r = report::get_ex_by_id(&db.conn, id, &policy::audience(&current_user)).await?;
     let r = match r {
         Some(v) => v,
         None => return Err(ResponseError::NotFound("report not found".to_string())),
@@ -423,7 +886,7 @@ async fn like_report(
 
     if r.author_id == current_user.id {
         tracing::warn!(user = %current_user.name, report_id = id, "attempted to like own report");
-        return Err(ResponseError::BadRequest("cannot like your own report".to_string()));
+        return Err(ResponseError::CannotLikeOwnReport);
     }
 
     // parse likes JSON array
@@ -433,7 +896,7 @@ async fn like_report(
     };
 
     if likes.iter().any(|n| n == &current_user.name) {
-        return Err(ResponseError::BadRequest("already liked".to_string()));
+        return Err(ResponseError::AlreadyLiked);
     }
 
     likes.push(current_user.name.clone());
@@ -443,7 +906,8 @@ async fn like_report(
     match report::update_likes_by_id(&db.conn, id, Some(likes_s.clone())).await {
         Ok(_) => {
             tracing::info!(user = %current_user.name, report_id = id, likes_count = likes.len(), "like_report success");
-            Ok(Json(serde_json::json!({ "likes": likes })))
+            publish_like(db, &events, &r, &current_user, true, likes.clone()).await;
+            Ok(Json(LikesDto { likes }))
         }
             Err(e) => {
             tracing::error!(error = %e, user = %current_user.name, report_id = id, "like_report db error");
@@ -452,12 +916,24 @@ async fn like_report(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/report/{id}/unlike",
+    tag = "report",
+    params(("id" = i32, Path, description = "Report id")),
+    responses(
+        (status = 200, body = LikesDto),
+        (status = 400, description = "`cannot_unlike_own_report`", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
 async fn unlike_report(
     State(ref db): State<Database>,
+    State(events): State<EventBus>,
     Extension(current_user): Extension<user::Model>,
-    axum::extract::Path(id): axum::extract::Path<i32>,
+    Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    let r = report::get_by_id(&db.conn, id).await?;
+    let r = report::get_ex_by_id(&db.conn, id, &policy::audience(&current_user)).await?;
     let r = match r {
         Some(v) => v,
         None => return Err(ResponseError::NotFound("report not found".to_string())),
@@ -468,7 +944,7 @@ async fn unlike_report(
     // cannot unlike your own report
     if r.author_id == current_user.id {
         tracing::warn!(user = %current_user.name, report_id = id, "attempted to unlike own report");
-        return Err(ResponseError::BadRequest("cannot unlike your own report".to_string()));
+        return Err(ResponseError::CannotUnlikeOwnReport);
     }
 
     let mut likes: Vec<String> = match r.likes.clone() {
@@ -484,7 +960,8 @@ async fn unlike_report(
     match report::update_likes_by_id(&db.conn, id, Some(likes_s.clone())).await {
         Ok(_) => {
             tracing::info!(user = %current_user.name, report_id = id, likes_count = likes.len(), "unlike_report success");
-            Ok(Json(serde_json::json!({ "likes": likes })))
+            publish_like(db, &events, &r, &current_user, false, likes.clone()).await;
+            Ok(Json(LikesDto { likes }))
         }
         Err(e) => {
             tracing::error!(error = %e, user = %current_user.name, report_id = id, "unlike_report db error");
@@ -493,6 +970,25 @@ async fn unlike_report(
     }
 }
 
+/// Tell subscribers about a changed like. Filtering the event needs the
+/// author, failing to load them only costs the live update.
+async fn publish_like(
+    db: &Database,
+    events: &EventBus,
+    report: &ExModel,
+    user: &user::Model,
+    liked: bool,
+    likes: Vec<String>,
+) {
+    match user::get(&db.conn, report.author_id).await {
+        Ok(Some(author)) => events.publish(Event::ReportLiked(LikeEvent::new(
+            report, &author, user, liked, likes,
+        ))),
+        Ok(None) => {}
+        Err(e) => tracing::error!(report_id = report.id, "failed to publish like: {e}"),
+    }
+}
+
 /// Build RSS 2.0 XML for given user and reports. Description is wrapped in CDATA and
 /// any occurrence of `]]>` inside content is safely split.
 pub fn build_rss_feed(
@@ -526,5 +1022,57 @@ pub fn build_rss_feed(
     )
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    fn form(json: &str) -> ModifyUserForm {
+        serde_json::from_str(json).unwrap()
+    }
 
+    #[test]
+    fn modify_user_form_keeps_absent_null_and_values_apart() {
+        let ModifyUserForm { id, patch } = form(r#"{"id": 3, "direction": null, "level": 1}"#);
+        assert_eq!(id, 3);
+        assert_eq!(patch.direction, Some(None));
+        assert_eq!(patch.email, None);
+        assert_eq!(patch.level, Some(1));
+        assert_eq!(patch.fields(), ["direction", "level"]);
+    }
+
+    #[test]
+    fn members_may_only_change_their_own_self_editable_fields() {
+        let member = user::Model {
+            id: 1,
+            name: "member".to_string(),
+            ..Default::default()
+        };
+        let own = form(r#"{"id": 1, "direction": "web", "default_visibility": "team"}"#);
+        assert!(check_patch_allowed(&member, 1, &own.patch).is_ok());
+        // not even self-editable fields of someone else
+        assert!(matches!(
+            check_patch_allowed(&member, 2, &own.patch),
+            Err(ResponseError::Forbidden(..))
+        ));
+        for field in [
+            r#""level": 2"#,
+            r#""role": "direction_lead""#,
+            r#""is_admin": true"#,
+            r#""name": "x""#,
+        ] {
+            let patch = form(&format!(r#"{{"id": 1, "direction": null, {field}}}"#)).patch;
+            let Err(ResponseError::Forbidden(summary, _)) = check_patch_allowed(&member, 1, &patch)
+            else {
+                panic!("{field} was allowed");
+            };
+            assert!(!summary.contains("direction"), "{summary}");
+        }
+
+        let admin = user::Model {
+            is_admin: true,
+            ..member.clone()
+        };
+        let patch = form(r#"{"id": 2, "level": 2, "is_banned": true}"#).patch;
+        assert!(check_patch_allowed(&admin, 2, &patch).is_ok());
+    }
+}
@@ -0,0 +1,119 @@
+//! OpenAPI description of the API, derived from the `#[utoipa::path]`
+//! annotations of the handlers. Schemas are collected from the paths.
+//!
+//! A copy is committed at `docs/openapi.json` for the web client, the test
+//! below fails whenever the handlers drift from it. Regenerate it with
+//! `wr-server openapi > docs/openapi.json`.
+
+use std::sync::OnceLock;
+
+use axum::Json;
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+        OpenApi as Spec,
+    },
+    Modify, OpenApi,
+};
+
+use super::{audit, events, feed_token, import, stats, v2};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "XDSEC Weekly Report",
+        description = "Backend API of XDSEC Weekly Report.",
+        license(name = "Proprietary"),
+    ),
+    servers((url = "/api")),
+    paths(
+        super::ping,
+        super::get_status,
+        super::get_config,
+        super::update_config,
+        super::get_self_info,
+        super::get_user,
+        super::modify_user,
+        super::ban_user,
+        super::unban_user,
+        import::import_users,
+        super::get_report,
+        super::handle_submit,
+        super::like_report,
+        super::unlike_report,
+        events::subscribe,
+        super::get_public_report,
+        v2::get_report,
+        v2::get_user_reports,
+        v2::get_week_reports,
+        v2::get_index,
+        super::get_user_feed,
+        super::get_public_user_feed,
+        feed_token::list_tokens,
+        feed_token::create_token,
+        feed_token::revoke_token,
+        feed_token::regenerate_token,
+        audit::get_audit_logs,
+        stats::get_overview,
+        stats::get_weeks,
+        stats::get_user_stats,
+    ),
+    components(schemas(
+        crate::events::ReportEvent,
+        crate::events::LikeEvent,
+        crate::events::StatusEvent,
+    )),
+    modifiers(&ForwardAuth),
+    security(("forward_auth" = [])),
+    tags(
+        (name = "status", description = "Liveness and submission status"),
+        (name = "config", description = "Term configuration"),
+        (name = "user", description = "Users and their accounts"),
+        (name = "report", description = "Weekly reports and likes"),
+        (name = "feed", description = "RSS feeds and feed tokens"),
+        (name = "audit", description = "Audit log"),
+        (name = "stats", description = "Term statistics"),
+    ),
+)]
+struct ApiDoc;
+
+/// Login is handled by the reverse proxy in front of the server, which passes
+/// the user on in the `x-email` (or `x-nickname`) header.
+struct ForwardAuth;
+
+impl Modify for ForwardAuth {
+    fn modify(&self, openapi: &mut Spec) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "forward_auth",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "x-email",
+                "URL-encoded email of the user, set by the authenticating reverse proxy",
+            ))),
+        );
+    }
+}
+
+pub fn spec() -> &'static Spec {
+    static SPEC: OnceLock<Spec> = OnceLock::new();
+    SPEC.get_or_init(ApiDoc::openapi)
+}
+
+pub async fn get_openapi() -> Json<&'static Spec> {
+    Json(spec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_spec_is_up_to_date() {
+        let committed = include_str!("../../../../docs/openapi.json");
+        let current = spec().to_pretty_json().unwrap();
+        assert!(
+            committed.trim_end() == current.trim_end(),
+            "docs/openapi.json is outdated, run `cargo run --bin wr-server -- openapi > docs/openapi.json`"
+        );
+    }
+}
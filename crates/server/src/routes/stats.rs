@@ -0,0 +1,416 @@
+//! Term statistics for admins, as JSON or, with `?format=csv`, as a CSV
+//! download of the tabular part.
+//!
+//! The term runs from the configured `begin_week` up to the current week,
+//! without the skipped weeks. Without a configuration it starts with the first
+//! week that has reports. Submission rates are relative to the users who are
+//! not hidden today, there is no history of membership.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{Datelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use wr_database::{
+    config,
+    stats::{self, LikedReport},
+    user, Database,
+};
+
+use super::extract::{Json, Path, Query};
+use crate::{traits::ErrorBody, ResponseError};
+
+/// Reports listed as the most liked in the overview.
+const TOP_REPORTS: u64 = 10;
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormatQuery {
+    #[serde(default)]
+    format: Format,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WeeksQuery {
+    /// First week, defaults to the beginning of the term.
+    from: Option<i32>,
+    /// Last week (inclusive), defaults to the current week.
+    to: Option<i32>,
+    #[serde(default)]
+    format: Format,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OverviewDto {
+    members: i64,
+    /// Weeks of the term so far.
+    weeks: usize,
+    reports: i64,
+    /// Reports per member and week, `null` before the first week.
+    submission_rate: Option<f64>,
+    /// Characters per report.
+    average_length: Option<f64>,
+    likes: i64,
+    directions: Vec<DirectionDto>,
+    top_reports: Vec<LikedReport>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DirectionDto {
+    direction: Option<String>,
+    members: i64,
+    reports: i64,
+    submission_rate: Option<f64>,
+    average_length: Option<f64>,
+    likes: i64,
+}
+
+/// A row of the overview CSV, the whole term first and then each direction.
+#[derive(Serialize)]
+struct OverviewRow<'a> {
+    scope: &'static str,
+    direction: Option<&'a str>,
+    members: i64,
+    reports: i64,
+    submission_rate: Option<f64>,
+    average_length: Option<f64>,
+    likes: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WeeksDto {
+    members: i64,
+    weeks: Vec<WeekDto>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WeekDto {
+    week: i32,
+    reports: i64,
+    submission_rate: Option<f64>,
+    average_length: Option<f64>,
+    likes: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserStatsDto {
+    user_id: i32,
+    name: String,
+    direction: Option<String>,
+    /// All reports of the user, including those outside the term.
+    reports: usize,
+    average_length: Option<f64>,
+    /// Likes received.
+    likes: i64,
+    /// Share of the term's weeks with a report.
+    submission_rate: Option<f64>,
+    /// Consecutive weeks with a report up to the current one.
+    current_streak: usize,
+    longest_streak: usize,
+    weeks: Vec<UserWeekDto>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserWeekDto {
+    week: i32,
+    submitted: bool,
+    length: i64,
+    likes: i64,
+}
+
+fn ratio(part: i64, whole: i64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 / whole as f64)
+}
+
+/// Weeks of the term up to today.
+async fn term(db: &Database) -> Result<Vec<i32>, ResponseError> {
+    let today = Utc::now().date_naive();
+    let (begin, skip) = match config::get(&db.conn).await? {
+        Some(c) if c.begin_week > 0 => (
+            c.begin_week,
+            c.skip_weeks
+                .and_then(|s| serde_json::from_str::<Vec<i32>>(&s).ok())
+                .unwrap_or_default(),
+        ),
+        _ => match stats::week_activity(&db.conn, None, None).await?.first() {
+            Some(first) => (first.week, vec![]),
+            None => return Ok(vec![]),
+        },
+    };
+    Ok(stats::term_weeks(begin, &skip, today))
+}
+
+fn csv_response<T: Serialize>(
+    rows: impl IntoIterator<Item = T>,
+    file_name: &str,
+) -> Result<Response, ResponseError> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row).map_err(|e| {
+            ResponseError::InternalServerError("failed to write csv".to_string(), e.to_string())
+        })?;
+    }
+    let data = writer.into_inner().map_err(|e| {
+        ResponseError::InternalServerError("failed to write csv".to_string(), e.to_string())
+    })?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}.csv\""),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/overview",
+    tag = "stats",
+    params(FormatQuery),
+    responses(
+        (status = 200, body = OverviewDto),
+        (status = 200, description = "With `format=csv`", body = String, content_type = "text/csv"),
+        (status = 403, description = "Missing `view_stats`", body = ErrorBody),
+    ),
+)]
+pub async fn get_overview(
+    State(ref db): State<Database>,
+    Query(query): Query<FormatQuery>,
+) -> Result<Response, ResponseError> {
+    let weeks = term(db).await?;
+    let members = stats::direction_members(&db.conn).await?;
+    let activity =
+        stats::direction_activity(&db.conn, weeks.first().copied(), weeks.last().copied()).await?;
+
+    let mut directions: BTreeMap<Option<String>, DirectionDto> = BTreeMap::new();
+    for m in members {
+        directions.insert(
+            m.direction.clone(),
+            DirectionDto {
+                direction: m.direction,
+                members: m.members,
+                reports: 0,
+                submission_rate: None,
+                average_length: None,
+                likes: 0,
+            },
+        );
+    }
+    let (mut reports, mut length, mut likes) = (0, 0, 0);
+    for a in activity {
+        reports += a.reports;
+        length += a.length;
+        likes += a.likes;
+        let dto = directions
+            .entry(a.direction.clone())
+            .or_insert_with(|| DirectionDto {
+                direction: a.direction,
+                members: 0,
+                reports: 0,
+                submission_rate: None,
+                average_length: None,
+                likes: 0,
+            });
+        dto.reports = a.reports;
+        dto.average_length = ratio(a.length, a.reports);
+        dto.likes = a.likes;
+    }
+    let directions: Vec<_> = directions
+        .into_values()
+        .map(|d| DirectionDto {
+            submission_rate: ratio(d.reports, d.members * weeks.len() as i64),
+            ..d
+        })
+        .collect();
+    let members = directions.iter().map(|d| d.members).sum::<i64>();
+    let overview = OverviewDto {
+        members,
+        weeks: weeks.len(),
+        reports,
+        submission_rate: ratio(reports, members * weeks.len() as i64),
+        average_length: ratio(length, reports),
+        likes,
+        directions,
+        top_reports: stats::most_liked(&db.conn, TOP_REPORTS).await?,
+    };
+
+    match query.format {
+        Format::Json => Ok(Json(overview).into_response()),
+        Format::Csv => {
+            let total = OverviewRow {
+                scope: "term",
+                direction: None,
+                members: overview.members,
+                reports: overview.reports,
+                submission_rate: overview.submission_rate,
+                average_length: overview.average_length,
+                likes: overview.likes,
+            };
+            let rows = overview.directions.iter().map(|d| OverviewRow {
+                scope: "direction",
+                direction: d.direction.as_deref(),
+                members: d.members,
+                reports: d.reports,
+                submission_rate: d.submission_rate,
+                average_length: d.average_length,
+                likes: d.likes,
+            });
+            csv_response(std::iter::once(total).chain(rows), "overview")
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/weeks",
+    tag = "stats",
+    params(WeeksQuery),
+    responses(
+        (status = 200, body = WeeksDto),
+        (status = 200, description = "With `format=csv`", body = String, content_type = "text/csv"),
+        (status = 400, body = ErrorBody),
+        (status = 403, description = "Missing `view_stats`", body = ErrorBody),
+    ),
+)]
+pub async fn get_weeks(
+    State(ref db): State<Database>,
+    Query(query): Query<WeeksQuery>,
+) -> Result<Response, ResponseError> {
+    for (name, week) in [("from", query.from), ("to", query.to)] {
+        if week.is_some_and(|w| stats::week_date(w).is_none()) {
+            return Err(ResponseError::BadRequest(format!(
+                "`{name}` must be a yyyymmdd date"
+            )));
+        }
+    }
+    let term = term(db).await?;
+    let from = query.from.or(term.first().copied());
+    let to = query
+        .to
+        .unwrap_or_else(|| stats::last_week(Utc::now().date_naive()));
+    let members = stats::direction_members(&db.conn)
+        .await?
+        .iter()
+        .map(|m| m.members)
+        .sum();
+
+    // weeks of the term without reports are listed too
+    let mut weeks: BTreeMap<i32, WeekDto> = term
+        .into_iter()
+        .filter(|w| from.map_or(true, |from| *w >= from) && *w <= to)
+        .map(|week| {
+            let dto = WeekDto {
+                week,
+                reports: 0,
+                submission_rate: Some(0.0).filter(|_| members > 0),
+                average_length: None,
+                likes: 0,
+            };
+            (week, dto)
+        })
+        .collect();
+    for a in stats::week_activity(&db.conn, from, Some(to)).await? {
+        weeks.insert(
+            a.week,
+            WeekDto {
+                week: a.week,
+                reports: a.reports,
+                submission_rate: ratio(a.reports, members),
+                average_length: ratio(a.length, a.reports),
+                likes: a.likes,
+            },
+        );
+    }
+    let weeks = weeks.into_values().collect();
+
+    match query.format {
+        Format::Json => Ok(Json(WeeksDto { members, weeks }).into_response()),
+        Format::Csv => csv_response(weeks, "weeks"),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/user/{id}",
+    tag = "stats",
+    params(("id" = i32, Path, description = "User id"), FormatQuery),
+    responses(
+        (status = 200, body = UserStatsDto),
+        (status = 200, description = "With `format=csv`, the weeks", body = String, content_type = "text/csv"),
+        (status = 403, description = "Missing `view_stats`", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn get_user_stats(
+    State(ref db): State<Database>,
+    Path(id): Path<i32>,
+    Query(query): Query<FormatQuery>,
+) -> Result<Response, ResponseError> {
+    let Some(user) = user::get(&db.conn, id).await? else {
+        return Err(ResponseError::NotFound("user not found".to_string()));
+    };
+    let reports = stats::user_reports(&db.conn, id).await?;
+    let by_week: BTreeMap<i32, &stats::ReportSize> = reports.iter().map(|r| (r.week, r)).collect();
+
+    let mut term = term(db).await?;
+    let submitted: BTreeSet<i32> = by_week.keys().copied().collect();
+    // the current week is still open, it only counts once submitted
+    if Utc::now().weekday() == Weekday::Sun && term.last().is_some_and(|w| !submitted.contains(w)) {
+        term.pop();
+    }
+    let streaks = stats::streaks(&term, &submitted);
+    let in_term = term.iter().filter(|w| submitted.contains(w)).count();
+
+    let weeks: Vec<_> = term
+        .iter()
+        .map(|week| match by_week.get(week) {
+            Some(r) => UserWeekDto {
+                week: *week,
+                submitted: true,
+                length: r.length,
+                likes: r.likes,
+            },
+            None => UserWeekDto {
+                week: *week,
+                submitted: false,
+                length: 0,
+                likes: 0,
+            },
+        })
+        .collect();
+
+    match query.format {
+        Format::Json => Ok(Json(UserStatsDto {
+            user_id: user.id,
+            name: user.name,
+            direction: user.direction,
+            reports: reports.len(),
+            average_length: ratio(reports.iter().map(|r| r.length).sum(), reports.len() as i64),
+            likes: reports.iter().map(|r| r.likes).sum(),
+            submission_rate: ratio(in_term as i64, term.len() as i64),
+            current_streak: streaks.current,
+            longest_streak: streaks.longest,
+            weeks,
+        })
+        .into_response()),
+        Format::Csv => csv_response(weeks, &format!("user-{id}")),
+    }
+}
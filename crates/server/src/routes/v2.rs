@@ -0,0 +1,238 @@
+//! Version 2 of the report API: one resource per route, every response is an
+//! object with named fields and lists share the same pagination block.
+//!
+//! The query-driven `GET /api/report` stays available for the web client
+//! until it has moved over.
+
+use axum::{extract::State, response::IntoResponse, routing::get, Extension, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use wr_database::{report, user, Database};
+
+use super::{
+    exmodel_to_dto,
+    extract::{Json, Path, Query},
+    model_to_dto, ExReportDto, ReportDto,
+};
+use crate::{
+    policy::{self, UserDto},
+    traits::{ErrorBody, GlobalState},
+    ResponseError,
+};
+
+const DEFAULT_PER_PAGE: u64 = 50;
+const MAX_PER_PAGE: u64 = 200;
+
+pub fn router() -> Router<GlobalState> {
+    Router::new()
+        .route("/reports/{id}", get(get_report))
+        .route("/users/{id}/reports", get(get_user_reports))
+        .route("/weeks/{week}/reports", get(get_week_reports))
+        .route("/index", get(get_index))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// 1-based page number.
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+impl PageQuery {
+    /// The 1-based page and the clamped page size.
+    fn resolve(&self) -> (u64, u64) {
+        let page = self.page.unwrap_or(1).max(1);
+        let per_page = self
+            .per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE);
+        (page, per_page)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Pagination {
+    /// 1-based page number.
+    page: u64,
+    per_page: u64,
+    /// Number of items on all pages.
+    total: u64,
+    /// Number of pages, at least 1.
+    pages: u64,
+}
+
+impl Pagination {
+    fn new(page: u64, per_page: u64, total: u64) -> Self {
+        Self {
+            page,
+            per_page,
+            total,
+            pages: total.div_ceil(per_page).max(1),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReportEnvelope {
+    report: ExReportDto,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserReportsEnvelope {
+    author: UserDto,
+    /// Reports without their content, newest week first.
+    reports: Vec<ReportDto>,
+    pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WeekReportsEnvelope {
+    /// Week, as the `yyyymmdd` date of its Sunday.
+    week: i32,
+    /// Reports without their content, ordered by author.
+    reports: Vec<ExReportDto>,
+    pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IndexEnvelope {
+    users: Vec<UserDto>,
+    /// Reports of the last six weeks, without their content.
+    reports: Vec<ReportDto>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/reports/{id}",
+    tag = "report",
+    params(("id" = i32, Path, description = "Report id")),
+    responses(
+        (status = 200, body = ReportEnvelope),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn get_report(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let audience = policy::audience(&current_user);
+    match report::get_ex_by_id(&db.conn, id, &audience).await? {
+        Some(r) => Ok(Json(ReportEnvelope {
+            report: exmodel_to_dto(r),
+        })),
+        None => Err(ResponseError::NotFound("report not found".to_string())),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/users/{id}/reports",
+    tag = "report",
+    params(("id" = i32, Path, description = "Author id"), PageQuery),
+    responses(
+        (status = 200, body = UserReportsEnvelope),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn get_user_reports(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Query(query): Query<PageQuery>,
+) -> Result<impl IntoResponse, ResponseError> {
+    // hidden users look the same as missing ones to those who cannot see them
+    let Some(author) = user::get(&db.conn, id)
+        .await?
+        .filter(|u| policy::can_see_user(&current_user, u))
+    else {
+        return Err(ResponseError::NotFound("user not found".to_string()));
+    };
+    let (page, per_page) = query.resolve();
+    let audience = policy::audience(&current_user);
+    let (reports, total) =
+        report::get_user_page(&db.conn, id, &audience, page - 1, per_page).await?;
+    Ok(Json(UserReportsEnvelope {
+        author: policy::project_user(&current_user, author),
+        reports: reports.into_iter().map(model_to_dto).collect(),
+        pagination: Pagination::new(page, per_page, total),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/weeks/{week}/reports",
+    tag = "report",
+    params(
+        ("week" = i32, Path, description = "Week, as the `yyyymmdd` date of its Sunday"),
+        PageQuery,
+    ),
+    responses((status = 200, body = WeekReportsEnvelope)),
+)]
+pub async fn get_week_reports(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+    Path(week): Path<i32>,
+    Query(query): Query<PageQuery>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let (page, per_page) = query.resolve();
+    let audience = policy::audience(&current_user);
+    let (reports, total) =
+        report::get_week_page(&db.conn, week, &audience, page - 1, per_page).await?;
+    Ok(Json(WeekReportsEnvelope {
+        week,
+        reports: reports.into_iter().map(exmodel_to_dto).collect(),
+        pagination: Pagination::new(page, per_page, total),
+    }))
+}
+
+/// Users and recent reports for the index table.
+#[utoipa::path(
+    get,
+    path = "/v2/index",
+    tag = "report",
+    responses((status = 200, body = IndexEnvelope)),
+)]
+pub async fn get_index(
+    State(ref db): State<Database>,
+    Extension(current_user): Extension<user::Model>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let audience = policy::audience(&current_user);
+    let reports = report::get_index_list(&db.conn, &audience).await?;
+    let users = user::get_list(&db.conn, false)
+        .await?
+        .into_iter()
+        .map(|u| policy::project_user(&current_user, u))
+        .collect();
+    Ok(Json(IndexEnvelope {
+        users,
+        reports: reports.into_iter().map(model_to_dto).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pagination_counts_pages() {
+        assert_eq!(Pagination::new(1, 50, 0).pages, 1);
+        assert_eq!(Pagination::new(1, 50, 50).pages, 1);
+        assert_eq!(Pagination::new(2, 50, 51).pages, 2);
+    }
+
+    #[test]
+    fn page_query_is_clamped() {
+        let query = PageQuery {
+            page: Some(0),
+            per_page: Some(10_000),
+        };
+        assert_eq!(query.resolve(), (1, MAX_PER_PAGE));
+        let query = PageQuery {
+            page: None,
+            per_page: Some(0),
+        };
+        assert_eq!(query.resolve(), (1, 1));
+    }
+}
@@ -1,16 +1,31 @@
+use std::sync::Arc;
+
 use axum::{
     body::Body,
     extract::FromRef,
     http::{header::ToStrError, StatusCode},
     response::{IntoResponse, Response},
+    Json,
 };
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, warn};
+use utoipa::ToSchema;
 use wr_database::{Database, DbErr};
 
+use crate::{events::EventBus, middleware::request_id, settings::Settings};
+
 #[derive(Clone, FromRef)]
 pub struct GlobalState {
     pub db: Database,
+    pub settings: Arc<Settings>,
+    pub events: EventBus,
+    /// Cancelled when the server shuts down, long-lived responses such as
+    /// event streams end with it.
+    pub shutdown: CancellationToken,
     pub version: String,
 }
 
@@ -32,8 +47,24 @@ pub enum ResponseError {
     Conflict(String),
     #[error("precondition failed: {0}")]
     PreconditionFailed(String),
+    #[error("payload too large: {0}")]
+    PayloadTooLarge(String),
     #[error("too many requests: {0}, {1}")]
     TooManyRequests(String, String),
+    #[error("account banned: {detail}")]
+    Banned {
+        until: Option<DateTime<Utc>>,
+        reason: Option<String>,
+        detail: String,
+    },
+    #[error("reports can only be submitted on Sunday")]
+    SubmissionWindowClosed,
+    #[error("cannot like your own report")]
+    CannotLikeOwnReport,
+    #[error("cannot unlike your own report")]
+    CannotUnlikeOwnReport,
+    #[error("already liked")]
+    AlreadyLiked,
     #[error("database error: {0}")]
     DatabaseError(#[from] wr_database::DbErr),
     #[error("serialize error: {0}")]
@@ -48,6 +79,50 @@ pub enum ResponseError {
     FromUtf8Error(#[from] std::string::FromUtf8Error),
 }
 
+impl ResponseError {
+    /// Stable machine readable code of the error. The web frontend keeps a
+    /// translated message for each code under `errors.codes.<code>`, so codes
+    /// must never be renamed.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ResponseError::InternalServerError(..) => "internal_error",
+            ResponseError::Unauthorized(_) => "unauthorized",
+            ResponseError::BadRequest(_) => "bad_request",
+            ResponseError::Forbidden(..) => "forbidden",
+            ResponseError::NotFound(_) => "not_found",
+            ResponseError::Gone(_) => "gone",
+            ResponseError::Conflict(_) => "conflict",
+            ResponseError::PreconditionFailed(_) => "precondition_failed",
+            ResponseError::PayloadTooLarge(_) => "payload_too_large",
+            ResponseError::TooManyRequests(..) => "too_many_requests",
+            ResponseError::Banned { .. } => "account_banned",
+            ResponseError::SubmissionWindowClosed => "submission_window_closed",
+            ResponseError::CannotLikeOwnReport => "cannot_like_own_report",
+            ResponseError::CannotUnlikeOwnReport => "cannot_unlike_own_report",
+            ResponseError::AlreadyLiked => "already_liked",
+            ResponseError::DatabaseError(DbErr::RecordNotFound(_)) => "not_found",
+            ResponseError::DatabaseError(_) => "database_error",
+            ResponseError::SerializeError(_)
+            | ResponseError::FileIoError(_)
+            | ResponseError::FromUtf8Error(_) => "internal_error",
+            ResponseError::ParseIntError(_) => "invalid_integer",
+            ResponseError::ToStrError(_) => "invalid_header",
+        }
+    }
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    code: &'static str,
+    /// English message for humans, clients should prefer translating `code`.
+    message: String,
+    /// Values for the translated message, e.g. the end of a ban.
+    #[schema(value_type = Option<Object>)]
+    details: Option<Value>,
+    request_id: Option<String>,
+}
+
 macro_rules! log_with_resp {
     ($code:expr, $summary:expr, $detail:expr) => {{
         if ($code).is_server_error() {
@@ -55,33 +130,62 @@ macro_rules! log_with_resp {
         } else {
             warn!("{}: {}", $summary, $detail);
         }
-        ($code, $summary)
+        ($code, $summary, None)
     }};
 }
 
 impl IntoResponse for ResponseError {
     fn into_response(self) -> Response<Body> {
-        let (status, message) = match self {
+        let code = self.code();
+        let (status, message, details) = match self {
             ResponseError::InternalServerError(summary, detail) => {
                 log_with_resp!(StatusCode::INTERNAL_SERVER_ERROR, summary, detail)
             }
-            ResponseError::Unauthorized(summary) => (StatusCode::UNAUTHORIZED, summary),
-            ResponseError::BadRequest(summary) => (StatusCode::BAD_REQUEST, summary),
+            ResponseError::Unauthorized(summary) => (StatusCode::UNAUTHORIZED, summary, None),
+            ResponseError::BadRequest(summary) => (StatusCode::BAD_REQUEST, summary, None),
             ResponseError::Forbidden(summary, detail) => {
                 log_with_resp!(StatusCode::FORBIDDEN, summary, detail)
             }
-            ResponseError::NotFound(summary) => (StatusCode::NOT_FOUND, summary),
-            ResponseError::Conflict(summary) => (StatusCode::CONFLICT, summary),
+            ResponseError::NotFound(summary) => (StatusCode::NOT_FOUND, summary, None),
+            ResponseError::Conflict(summary) => (StatusCode::CONFLICT, summary, None),
             ResponseError::TooManyRequests(summary, detail) => {
                 log_with_resp!(StatusCode::TOO_MANY_REQUESTS, summary, detail)
             }
             ResponseError::PreconditionFailed(summary) => {
-                (StatusCode::PRECONDITION_FAILED, summary)
+                (StatusCode::PRECONDITION_FAILED, summary, None)
             }
-            ResponseError::DatabaseError(e) => match e {
-                DbErr::RecordNotFound(s) => {
-                    (StatusCode::NOT_FOUND, format!("record not found: {s}"))
+            ResponseError::PayloadTooLarge(summary) => {
+                (StatusCode::PAYLOAD_TOO_LARGE, summary, None)
+            }
+            ResponseError::Banned {
+                until,
+                reason,
+                detail,
+            } => {
+                let mut summary = match until {
+                    Some(until) => format!("your account is banned until {}", until.to_rfc3339()),
+                    None => "your account is banned".to_string(),
+                };
+                if let Some(reason) = &reason {
+                    summary = format!("{summary}: {reason}");
                 }
+                warn!("{}: {}", summary, detail);
+                let details = json!({
+                    "until": until.map(|t| t.timestamp()),
+                    "reason": reason,
+                });
+                (StatusCode::FORBIDDEN, summary, Some(details))
+            }
+            e @ (ResponseError::SubmissionWindowClosed
+            | ResponseError::CannotLikeOwnReport
+            | ResponseError::CannotUnlikeOwnReport
+            | ResponseError::AlreadyLiked) => (StatusCode::BAD_REQUEST, e.to_string(), None),
+            ResponseError::DatabaseError(e) => match e {
+                DbErr::RecordNotFound(s) => (
+                    StatusCode::NOT_FOUND,
+                    format!("record not found: {s}"),
+                    None,
+                ),
                 _ => log_with_resp!(
                     StatusCode::INTERNAL_SERVER_ERROR,
                     "database internal error".to_owned(),
@@ -95,7 +199,7 @@ impl IntoResponse for ResponseError {
                     e.to_string()
                 )
             }
-            ResponseError::Gone(summary) => (StatusCode::GONE, summary),
+            ResponseError::Gone(summary) => (StatusCode::GONE, summary, None),
             ResponseError::SerializeError(e) => {
                 log_with_resp!(
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -125,9 +229,123 @@ impl IntoResponse for ResponseError {
                 )
             }
         };
-        Response::builder()
-            .status(status)
-            .body(message.into())
-            .unwrap()
+        let body = ErrorBody {
+            code,
+            message,
+            details,
+            request_id: request_id::current().map(|id| id.to_string()),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn render(err: ResponseError) -> (StatusCode, Value) {
+        let resp = err.into_response();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn domain_errors_have_their_own_code() {
+        let (status, body) = render(ResponseError::SubmissionWindowClosed).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "submission_window_closed");
+        assert_eq!(body["request_id"], Value::Null);
+
+        let (status, body) = render(ResponseError::AlreadyLiked).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "already_liked");
+    }
+
+    #[tokio::test]
+    async fn internal_details_are_not_exposed() {
+        let (status, body) = render(ResponseError::InternalServerError(
+            "failed to update likes".to_string(),
+            "connection reset by peer".to_string(),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["message"], "failed to update likes");
+        assert!(!body.to_string().contains("connection reset"));
+    }
+
+    #[test]
+    fn every_code_is_translated() {
+        let s = String::new;
+        let errors = [
+            ResponseError::InternalServerError(s(), s()),
+            ResponseError::Unauthorized(s()),
+            ResponseError::BadRequest(s()),
+            ResponseError::Forbidden(s(), s()),
+            ResponseError::NotFound(s()),
+            ResponseError::Gone(s()),
+            ResponseError::Conflict(s()),
+            ResponseError::PreconditionFailed(s()),
+            ResponseError::PayloadTooLarge(s()),
+            ResponseError::TooManyRequests(s(), s()),
+            ResponseError::Banned {
+                until: None,
+                reason: None,
+                detail: s(),
+            },
+            ResponseError::SubmissionWindowClosed,
+            ResponseError::CannotLikeOwnReport,
+            ResponseError::CannotUnlikeOwnReport,
+            ResponseError::AlreadyLiked,
+            DbErr::RecordNotFound(s()).into(),
+            DbErr::Custom(s()).into(),
+            serde_json::from_str::<i32>("").unwrap_err().into(),
+            std::io::Error::other("").into(),
+            "".parse::<i32>().unwrap_err().into(),
+            axum::http::HeaderValue::from_bytes(b"\xff")
+                .unwrap()
+                .to_str()
+                .unwrap_err()
+                .into(),
+            String::from_utf8(vec![0xff]).unwrap_err().into(),
+        ];
+        let locales = [
+            (
+                "en-us",
+                include_str!("../../../web/src/lib/i18n/en-us.json"),
+            ),
+            (
+                "zh-cn",
+                include_str!("../../../web/src/lib/i18n/zh-cn.json"),
+            ),
+        ];
+        for (locale, json) in locales {
+            let messages: Value = serde_json::from_str(json).unwrap();
+            for error in &errors {
+                let code = error.code();
+                assert!(
+                    messages["errors"]["codes"][code].is_string(),
+                    "{locale} has no message for `{code}`"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn ban_details_are_machine_readable() {
+        let until = DateTime::from_timestamp(1_900_000_000, 0).unwrap();
+        let (status, body) = render(ResponseError::Banned {
+            until: Some(until),
+            reason: Some("spam".to_string()),
+            detail: String::new(),
+        })
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "account_banned");
+        assert_eq!(body["details"]["until"], 1_900_000_000);
+        assert_eq!(body["details"]["reason"], "spam");
     }
 }
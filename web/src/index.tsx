@@ -37,6 +37,8 @@ function checkEdition() {
 
 render(() => {
     checkEdition();
+    // older builds kept a feed token here, it is not tied to the user
+    localStorage.removeItem("feed_token");
     initTheme();
     onMount(() => {
         setTimeout(() => {
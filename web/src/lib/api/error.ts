@@ -0,0 +1,28 @@
+import { t } from "@storage/theme";
+import type { HTTPError } from "ky";
+
+// Body of every error response of the backend, see `ResponseError` in
+// crates/server/src/traits.rs.
+export interface ApiError {
+    code: string;
+    message: string;
+    details: Record<string, unknown> | null;
+    request_id: string | null;
+}
+
+export async function read_error(err: HTTPError): Promise<ApiError | null> {
+    try {
+        return (await err.response.clone().json()) as ApiError;
+    } catch (_) {
+        return null;
+    }
+}
+
+// Translate an error response through `errors.codes.<code>`, falling back to
+// the English message of the backend.
+export async function describe_error(err: HTTPError): Promise<string> {
+    const body = await read_error(err);
+    if (!body) return t("errors.unknown")!;
+    const text = t(`errors.codes.${body.code}` as any, (body.details ?? {}) as any) as string | undefined;
+    return text || body.message;
+}
@@ -1,4 +1,4 @@
-import type { Report } from "@models/report";
+import type { Report, Visibility } from "@models/report";
 import type { User } from "@models/user";
 import { luxonReplacer, luxonReviver } from "@models/utils";
 import ky from "ky";
@@ -12,6 +12,8 @@ const api = ky.extend({
 
 export default api;
 
+export { describe_error, read_error, type ApiError } from "./error";
+
 export async function get_reports() {
     const res = await api.get(`${api_root}/report`).json<any>();
     // backend returns [users, reports]
@@ -42,14 +44,38 @@ export async function get_self() {
     return await api.get(`${api_root}/self`).json<User>();
 }
 
-export async function get_self_feed_token() {
-    return await api.get(`${api_root}/self/feed_token`).json<{ token: string }>();
+// Feed tokens are only readable once, right after they are created. The
+// secret is handed to the caller to show and is never stored by the browser.
+export interface FeedToken {
+    id: number;
+    user_id: number;
+    label: string;
+    created_at: number;
+    last_used_at: number | null;
+    revoked_at: number | null;
+}
+
+export function feed_url(author: number, token: string) {
+    return `${location.origin.replace(/\/$/, "")}${api_root}/${author}/feed/?token=${encodeURIComponent(token)}`;
 }
 
+// Revokes every token of the current user and returns a fresh one.
 export async function regenerate_self_feed_token() {
     return await api.post(`${api_root}/self/feed_token`).json<{ token: string }>();
 }
 
+export async function list_self_feed_tokens() {
+    return await api.get(`${api_root}/self/feed_tokens`).json<FeedToken[]>();
+}
+
+export async function create_self_feed_token(label: string) {
+    return await api.post(`${api_root}/self/feed_tokens`, { json: { label } }).json<FeedToken & { token: string }>();
+}
+
+export async function revoke_self_feed_token(id: number) {
+    return await api.delete(`${api_root}/self/feed_tokens/${id}`).json<{ revoked: number }>();
+}
+
 export async function get_user_list(hidden: boolean) {
     return await api.get(`${api_root}/user?with_hidden=${hidden}`).json<User[]>();
 }
@@ -58,8 +84,8 @@ export async function get_user(user: number) {
     return await api.get(`${api_root}/user?id=${user}`).json<User>();
 }
 
-export async function submit_report(content: string) {
-    const res = await api.post(`${api_root}/report`, { json: { content } }).json<any>();
+export async function submit_report(content: string, visibility?: Visibility) {
+    const res = await api.post(`${api_root}/report`, { json: { content, visibility } }).json<any>();
     return normalizeReport(res) as Report;
 }
 
@@ -71,6 +97,21 @@ export async function unlike_report(reportId: number) {
     return await api.post(`${api_root}/report/${reportId}/unlike`).json<{ likes: string[] }>();
 }
 
+export type ServerEvent = "report.submitted" | "report.updated" | "report.liked" | "status.changed" | "resync";
+
+// Live updates from `/api/events`. `resync` means that events were dropped and
+// the page should reload its data. Returns a function that closes the stream.
+export function subscribe_events(handlers: Partial<Record<ServerEvent, (data: any) => void>>) {
+    const source = new EventSource(`${api_root}/events`);
+    for (const [name, handler] of Object.entries(handlers)) {
+        source.addEventListener(name, (e) => {
+            const text = (e as MessageEvent<string>).data;
+            handler(text ? JSON.parse(text, luxonReviver) : undefined);
+        });
+    }
+    return () => source.close();
+}
+
 function normalizeReport(r: any): Report {
     if (!r) return r;
     try {
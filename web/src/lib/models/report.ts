@@ -1,3 +1,5 @@
+export type Visibility = "private" | "team" | "public";
+
 export type Report = {
     id: number;
     author_id: number;
@@ -6,4 +8,5 @@ export type Report = {
     content: string | null;
     date: number;
     likes?: string[];
+    visibility: Visibility;
 };
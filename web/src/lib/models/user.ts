@@ -1,11 +1,20 @@
+import type { Visibility } from "./report";
+
+export type Role = "member" | "mentor" | "direction_lead" | "admin";
+
 export type User = {
     id: number;
     name: string;
-    email: string | null;
     direction: string | null;
     level: number;
-    is_banned: boolean;
+    role: Role;
     is_hidden: boolean;
     is_admin: boolean;
+    // account details, only present for yourself or when you manage users
+    email?: string | null;
+    is_banned?: boolean;
+    banned_until?: number | null;
+    ban_reason?: string | null;
+    default_visibility?: Visibility;
     recent_reports?: number[];
 };
@@ -3,8 +3,15 @@ import { t } from "@/lib/storage/theme";
 import rxSticker from "@assets/imgs/rx.webp";
 import { addToast } from "@storage/toast";
 import { accountStore } from "@storage/account";
-import { Show } from "solid-js";
-import { get_self_feed_token, regenerate_self_feed_token } from "@api";
+import { useSearchParams } from "@solidjs/router";
+import { For, Show, createResource, createSignal } from "solid-js";
+import {
+    create_self_feed_token,
+    feed_url,
+    list_self_feed_tokens,
+    regenerate_self_feed_token,
+    revoke_self_feed_token,
+} from "@api";
 import Button from "@widgets/button";
 
 
@@ -15,6 +22,40 @@ export default function () {
         t("platform.notImplementedTips2"),
         t("platform.notImplementedTips3"),
     ];
+    const [searchParams] = useSearchParams();
+    // The feed to link to, the report page sends readers here with `?author=`.
+    const author = () => {
+        const id = Number.parseInt(searchParams.author as string);
+        return id || accountStore.user?.id;
+    };
+    const [tokens, { refetch }] = createResource(
+        () => accountStore.user?.id,
+        () => list_self_feed_tokens(),
+    );
+    // The secret of a token is only returned when it is created, it is shown
+    // once and kept nowhere else.
+    const [fresh, setFresh] = createSignal(null as string | null);
+    const link = () => (fresh() && author() ? feed_url(author()!, fresh()!) : "");
+
+    const copy = async () => {
+        try {
+            await navigator.clipboard.writeText(link());
+            addToast({ level: "success", description: t("feed.copied")!, duration: 5000 });
+        } catch (e) {
+            addToast({ level: "error", description: t("feed.copyFailed")!, duration: 5000 });
+        }
+    };
+    const issue = async (create: () => Promise<{ token: string }>) => {
+        try {
+            const { token } = await create();
+            setFresh(token);
+            refetch();
+            await copy();
+        } catch (e) {
+            addToast({ level: "error", description: t("feed.tokenFetchFailed")!, duration: 5000 });
+        }
+    };
+
     return (
         <>
             <Title title={`${t("admin.title")} - ${t("platform.name")}`} />
@@ -32,35 +73,75 @@ export default function () {
                         <span>{t("feed.settings")}</span>
                     </h2>
                     <p class="text-sm text-muted mb-4">{t("feed.settingsIntro")}</p>
-                    <div class="flex items-center space-x-2">
-                        <Show when={accountStore.user} fallback={<span class="opacity-60">{t("feed.needLogin")}</span>}>
+                    <Show when={accountStore.user} fallback={<span class="opacity-60">{t("feed.needLogin")}</span>}>
+                        <div class="flex items-center space-x-2">
                             <span class="flex-1">{accountStore.user?.name} (id:{accountStore.user?.id})</span>
+                            <Button
+                                size="sm"
+                                onClick={() => {
+                                    const label = prompt(t("feed.label")!, "web");
+                                    if (label) issue(() => create_self_feed_token(label));
+                                }}
+                            >
+                                {t("feed.create")}
+                            </Button>
                             <Button
                                 size="sm"
                                 level="primary"
-                                onClick={async () => {
+                                onClick={() => {
                                     if (!confirm(t("feed.reset") + "?")) return;
-                                    try {
-                                        const resp = await regenerate_self_feed_token();
-                                        const token = resp?.token;
-                                        if (!token) {
-                                            addToast({ level: "error", description: t("feed.tokenFetchFailed")!, duration: 5000 });
-                                            return;
-                                        }
-                                        const base = location.origin;
-                                        const authorId = accountStore.user?.id;
-                                        const url = `${base.replace(/\/$/, "")}/api/${authorId}/feed/?token=${token}`;
-                                        await navigator.clipboard.writeText(url);
-                                        addToast({ level: "success", description: t("feed.resetSuccess")!, duration: 5000 });
-                                    } catch (e) {
-                                        addToast({ level: "error", description: t("feed.copyFailed")!, duration: 5000 });
-                                    }
+                                    issue(regenerate_self_feed_token);
                                 }}
                             >
                                 {t("feed.reset")}
                             </Button>
+                        </div>
+                        <Show when={fresh()}>
+                            <div class="mt-4 space-y-2">
+                                <p class="text-sm">{t("feed.shownOnce", { author: String(author()) })}</p>
+                                <div class="flex items-center space-x-2">
+                                    <input class="flex-1 input input-sm font-mono" readOnly value={link()} />
+                                    <Button size="sm" onClick={copy}>
+                                        {t("form.copy")}
+                                    </Button>
+                                </div>
+                            </div>
                         </Show>
-                    </div>
+                        <ul class="mt-4 space-y-1 text-sm">
+                            <For each={tokens()?.filter((token) => token.revoked_at === null)}>
+                                {(token) => (
+                                    <li class="flex items-center space-x-2">
+                                        <span class="flex-1">
+                                            {token.label}
+                                            <span class="opacity-60 ml-2">
+                                                {t("feed.lastUsed", {
+                                                    time: token.last_used_at
+                                                        ? new Date(token.last_used_at * 1000).toLocaleString()
+                                                        : "-",
+                                                })}
+                                            </span>
+                                        </span>
+                                        <Button
+                                            size="sm"
+                                            level="error"
+                                            ghost
+                                            onClick={async () => {
+                                                if (!confirm(t("feed.revoke") + "?")) return;
+                                                try {
+                                                    await revoke_self_feed_token(token.id);
+                                                    refetch();
+                                                } catch (e) {
+                                                    addToast({ level: "error", description: t("feed.revokeFailed")!, duration: 5000 });
+                                                }
+                                            }}
+                                        >
+                                            {t("feed.revoke")}
+                                        </Button>
+                                    </li>
+                                )}
+                            </For>
+                        </ul>
+                    </Show>
                 </div>
             </section>
         </>
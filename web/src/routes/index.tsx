@@ -1,4 +1,4 @@
-import { get_reports } from "@api";
+import { get_reports, describe_error, subscribe_events } from "@api";
 import type { User } from "@models/user";
 import { Title } from "@storage/header";
 import { t } from "@storage/theme";
@@ -7,42 +7,46 @@ import { getCurrentWeek } from "@lib/utils/time";
 import Link from "@widgets/link";
 import LoadingTips from "@widgets/loading-tips";
 import type { HTTPError } from "ky";
-import { For, Show, createSignal } from "solid-js";
+import { For, Show, createSignal, onCleanup } from "solid-js";
 
 export default function () {
     const [userStates, setUserStates] = createSignal([] as User[]);
     const [weeks, setWeeks] = createSignal([] as number[]);
     const [loading, setLoading] = createSignal(true);
-    const week_set = new Set<number>();
-    week_set.add(getCurrentWeek());
-    get_reports()
-        .then(([users, reports]) => {
-            for (const user of users) {
-                user.recent_reports = reports
-                    .filter((report) => report.author_id === user.id)
-                    .map((report) => {
-                        week_set.add(report.week);
-                        return report.week;
+    const load = () =>
+        get_reports()
+            .then(([users, reports]) => {
+                const week_set = new Set<number>();
+                week_set.add(getCurrentWeek());
+                for (const user of users) {
+                    user.recent_reports = reports
+                        .filter((report) => report.author_id === user.id)
+                        .map((report) => {
+                            week_set.add(report.week);
+                            return report.week;
+                        });
+                }
+                setUserStates(
+                    users.sort((a, b) => {
+                        if (a.direction !== b.direction) return a.direction!.localeCompare(b.direction!);
+                        return a.level - b.level;
+                    })
+                );
+                setWeeks(Array.from(week_set).sort((a, b) => a - b));
+            })
+            .catch((err: HTTPError) => {
+                describe_error(err).then((text) => {
+                    addToast({
+                        level: "error",
+                        description: text,
+                        duration: 5000,
                     });
-            }
-            setUserStates(
-                users.sort((a, b) => {
-                    if (a.direction !== b.direction) return a.direction!.localeCompare(b.direction!);
-                    return a.level - b.level;
-                })
-            );
-            setWeeks(Array.from(week_set).sort((a, b) => a - b));
-        })
-        .catch((err: HTTPError) => {
-            err.response.text().then((text) => {
-                addToast({
-                    level: "error",
-                    description: text,
-                    duration: 5000,
                 });
-            });
-        })
-        .finally(() => setLoading(false));
+            })
+            .finally(() => setLoading(false));
+    load();
+    // new submissions show up without a refresh
+    onCleanup(subscribe_events({ "report.submitted": load, resync: load }));
     return (
         <>
             <Title title={t("platform.name")!} />
@@ -1,4 +1,4 @@
-import { get_self } from "@api";
+import { get_self, describe_error } from "@api";
 import { setAccountStore } from "@storage/account";
 import { t, themeStore } from "@storage/theme";
 import { addToast } from "@storage/toast";
@@ -27,7 +27,7 @@ function TitleBar() {
             setAccountStore({ user });
         })
         .catch((err: HTTPError) => {
-            err.response.text().then((text) => {
+            describe_error(err).then((text) => {
                 addToast({
                     level: "error",
                     description: text,
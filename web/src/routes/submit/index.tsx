@@ -1,4 +1,4 @@
-import { get_report, submit_report } from "@api";
+import { get_report, submit_report, describe_error } from "@api";
 import { accountStore } from "@storage/account";
 import { Title } from "@storage/header";
 import { t } from "@storage/theme";
@@ -39,7 +39,7 @@ export default function () {
                 });
             })
             .catch((err: HTTPError) => {
-                err.response.text().then((text) => {
+                describe_error(err).then((text) => {
                     addToast({
                         level: "error",
                         description: text,
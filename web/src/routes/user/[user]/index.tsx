@@ -1,4 +1,4 @@
-import { get_report } from "@api";
+import { get_report, describe_error, read_error } from "@api";
 import type { Report } from "@models/report";
 import { t } from "@storage/theme";
 import { addToast } from "@storage/toast";
@@ -8,7 +8,7 @@ import { A, useNavigate, useParams, useSearchParams } from "@solidjs/router";
 import type { HTTPError } from "ky";
 import { Match, Switch, Show, createEffect, createSignal, untrack, createMemo } from "solid-js";
 import { accountStore } from "@storage/account";
-import { get_self_feed_token, regenerate_self_feed_token, like_report, unlike_report } from "@api";
+import { like_report, unlike_report } from "@api";
 
 export default function () {
     const params = useParams();
@@ -39,7 +39,7 @@ export default function () {
                         setLikes(Array.isArray(r?.likes) ? r.likes : []);
                     })
                     .catch((err: HTTPError) => {
-                        err.response.text().then((text) => {
+                        describe_error(err).then((text) => {
                             addToast({
                                 level: "error",
                                 description: text,
@@ -86,13 +86,10 @@ export default function () {
                                         }
                                         let url: string;
                                         if (accountStore.user) {
-                                            const resp = await get_self_feed_token();
-                                            const token = resp?.token;
-                                            if (!token) {
-                                                addToast({ level: "error", description: t("feed.tokenFetchFailed")!, duration: 5000 });
-                                                return;
-                                            }
-                                            url = `${base.replace(/\/$/, "")}/api/${authorId}/feed/?token=${token}`;
+                                            // tokens are shown once, in the subscription settings
+                                            addToast({ level: "info", description: t("feed.getToken")!, duration: 5000 });
+                                            navigate(`/admin?author=${authorId}`);
+                                            return;
                                         } else {
                                             const envSub = (import.meta.env.VITE_DEV_SUBSCRIBER as string) || "linlinzzo";
                                             const subscriberName = encodeURIComponent(envSub);
@@ -141,24 +138,15 @@ export default function () {
                                             // ky throws HTTPError on non-2xx
                                             const err = e as HTTPError;
                                             if (err && (err as any).response) {
-                                                try {
-                                                    const status = (err as any).response?.status as number | undefined;
-                                                    const textRaw = await (err as any).response?.text?.();
-                                                    const text = (textRaw || "").toString().trim().toLowerCase();
-                                                    if (text.includes("cannot like your own report")) {
-                                                        desc = t("like.self")!;
-                                                    } else if (text.includes("cannot unlike your own report")) {
-                                                        desc = t("like.unlikeSelf")!;
-                                                    } else if (text.includes("already liked")) {
-                                                        desc = t("like.already")!;
-                                                    } else if (typeof status === "number") {
-                                                        // Fallback to generic error messages by status code
-                                                        const generic = t(`errors.${status}` as any);
-                                                        if (generic) desc = generic as string;
-                                                        else desc = t("errors.unknown")!;
-                                                    }
-                                                } catch (_) {
-                                                    // ignore parse failures
+                                                const body = await read_error(err);
+                                                if (body?.code === "cannot_like_own_report") {
+                                                    desc = t("like.self")!;
+                                                } else if (body?.code === "cannot_unlike_own_report") {
+                                                    desc = t("like.unlikeSelf")!;
+                                                } else if (body?.code === "already_liked") {
+                                                    desc = t("like.already")!;
+                                                } else {
+                                                    desc = await describe_error(err);
                                                 }
                                             } else if (e instanceof TypeError) {
                                                 // likely network error from fetch/ky
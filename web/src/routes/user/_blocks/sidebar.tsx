@@ -1,4 +1,4 @@
-import { get_user, get_user_reports } from "@api";
+import { get_user, get_user_reports, describe_error } from "@api";
 import type { Report } from "@models/report";
 import type { User } from "@models/user";
 import { fullTheme, t } from "@storage/theme";
@@ -29,7 +29,7 @@ export default function () {
                     setReports(data.sort((a, b) => b.week - a.week));
                 })
                 .catch((err: HTTPError) => {
-                    err.response.text().then((text) => {
+                    describe_error(err).then((text) => {
                         addToast({
                             level: "error",
                             description: text,
@@ -43,7 +43,7 @@ export default function () {
                     else navigate("/sigtrap/404");
                 })
                 .catch((err: HTTPError) => {
-                    err.response.text().then((text) => {
+                    describe_error(err).then((text) => {
                         addToast({
                             level: "error",
                             description: text,
@@ -1,4 +1,4 @@
-import { get_report } from "@api";
+import { get_report, describe_error } from "@api";
 import type { Report } from "@models/report";
 import { t } from "@storage/theme";
 import { addToast } from "@storage/toast";
@@ -26,7 +26,7 @@ export default function () {
                 get_report(user, week)
                     .then(setReport)
                     .catch((err: HTTPError) => {
-                        err.response.text().then((text) => {
+                        describe_error(err).then((text) => {
                             addToast({
                                 level: "error",
                                 description: text,
@@ -1,4 +1,4 @@
-import { get_weekly_reports } from "@api";
+import { get_weekly_reports, describe_error } from "@api";
 import type { Report } from "@models/report";
 import { fullTheme, t } from "@storage/theme";
 import { addToast } from "@storage/toast";
@@ -27,7 +27,7 @@ export default function () {
                     setReports(data);
                 })
                 .catch((err: HTTPError) => {
-                    err.response.text().then((text) => {
+                    describe_error(err).then((text) => {
                         addToast({
                             level: "error",
                             description: text,